@@ -0,0 +1,208 @@
+use crate::math::Vec2f;
+
+// Deepest subdivision, particles that still share a cell here are kept together in one leaf
+const MAX_DEPTH: usize = 24;
+
+struct Node {
+    min: Vec2f,
+    size: f32,
+    weight: f32, //sum of the source weights (mass, charge) below this node
+    abs_weight: f32,
+    center: Vec2f, //center of the weights below this node, weighted by their magnitude
+    children: Option<[usize; 4]>,
+    sources: Vec<usize>, //only filled for leaves
+}
+
+impl Node {
+    fn new(min: Vec2f, size: f32) -> Self {
+        Node { min, size, weight: 0., abs_weight: 0., center: Vec2f::new(0., 0.), children: None, sources: Vec::new() }
+    }
+
+    fn quadrant(&self, position: Vec2f) -> usize {
+        let half = self.size / 2.0;
+        let right = (position.x >= self.min.x + half) as usize;
+        let top = (position.y >= self.min.y + half) as usize;
+        right + 2 * top
+    }
+
+    fn contains(&self, point: Vec2f) -> bool {
+        point.x >= self.min.x && point.x <= self.min.x + self.size && point.y >= self.min.y && point.y <= self.min.y + self.size
+    }
+}
+
+// Barnes-Hut quadtree over point sources. Distant groups of sources are approximated by their combined weight
+// at their center once the cell size over the distance drops below theta.
+pub struct QuadTree {
+    nodes: Vec<Node>,
+    positions: Vec<Vec2f>,
+    weights: Vec<f32>,
+}
+
+impl QuadTree {
+    pub fn build(positions: &[Vec2f], weights: &[f32]) -> Self {
+        let mut min = Vec2f::new(f32::MAX, f32::MAX);
+        let mut max = Vec2f::new(f32::MIN, f32::MIN);
+        for position in positions.iter().filter(|position| position.x.is_finite() && position.y.is_finite()) {
+            min = Vec2f::new(min.x.min(position.x), min.y.min(position.y));
+            max = Vec2f::new(max.x.max(position.x), max.y.max(position.y));
+        }
+        let size = (max.x - min.x).max(max.y - min.y).max(1.0);
+
+        let mut tree = QuadTree { nodes: vec![Node::new(min, size)], positions: positions.to_vec(), weights: weights.to_vec() };
+        for index in 0..positions.len() {
+            if positions[index].x.is_finite() && positions[index].y.is_finite() && weights[index] != 0. {
+                tree.insert(index);
+            }
+        }
+        tree.summarize(0);
+        tree
+    }
+
+    fn insert(&mut self, source: usize) {
+        let position = self.positions[source];
+        let mut node = 0;
+        let mut depth = 0;
+        loop {
+            if let Some(children) = self.nodes[node].children {
+                node = children[self.nodes[node].quadrant(position)];
+                depth += 1;
+                continue;
+            }
+            if self.nodes[node].sources.is_empty() || depth >= MAX_DEPTH {
+                self.nodes[node].sources.push(source);
+                return;
+            }
+            // split the occupied leaf and push its sources one level down
+            let half = self.nodes[node].size / 2.0;
+            let min = self.nodes[node].min;
+            let first_child = self.nodes.len();
+            for quadrant in 0..4 {
+                let offset = Vec2f::new((quadrant % 2) as f32 * half, (quadrant / 2) as f32 * half);
+                self.nodes.push(Node::new(min.add(&offset), half));
+            }
+            self.nodes[node].children = Some([first_child, first_child + 1, first_child + 2, first_child + 3]);
+            for existing in std::mem::take(&mut self.nodes[node].sources) {
+                let child = first_child + self.nodes[node].quadrant(self.positions[existing]);
+                self.nodes[child].sources.push(existing);
+            }
+        }
+    }
+
+    // fill in weight and center bottom up
+    fn summarize(&mut self, node: usize) {
+        let mut weight = 0.;
+        let mut abs_weight = 0.;
+        let mut moment = Vec2f::new(0., 0.);
+        if let Some(children) = self.nodes[node].children {
+            for child in children {
+                self.summarize(child);
+                let child = &self.nodes[child];
+                weight += child.weight;
+                abs_weight += child.abs_weight;
+                moment = moment.add(&child.center.scale(child.abs_weight));
+            }
+        } else {
+            for source in &self.nodes[node].sources {
+                weight += self.weights[*source];
+                abs_weight += self.weights[*source].abs();
+                moment = moment.add(&self.positions[*source].scale(self.weights[*source].abs()));
+            }
+        }
+        let node = &mut self.nodes[node];
+        node.weight = weight;
+        node.abs_weight = abs_weight;
+        if abs_weight > 0. {
+            node.center = moment.scale(1. / abs_weight);
+        }
+    }
+
+    // sum of weight * (source - point) / (r^2 + softening^2)^(3/2) over every source except `skip`,
+    // the acceleration towards the sources for an inverse square attraction of unit strength
+    pub fn field_at(&self, point: Vec2f, skip: usize, theta: f32, softening: f32) -> Vec2f {
+        let softening_squared = softening * softening;
+        let contribution = |center: Vec2f, weight: f32| {
+            let delta = center.subtract(&point);
+            let distance_squared = delta.dot(&delta) + softening_squared;
+            if distance_squared <= f32::EPSILON {
+                return Vec2f::new(0., 0.);
+            }
+            delta.scale(weight / (distance_squared * distance_squared.sqrt()))
+        };
+
+        let mut field = Vec2f::new(0., 0.);
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            if node.abs_weight == 0. {
+                continue;
+            }
+            match node.children {
+                Some(children) => {
+                    // a node around the point holds the source at the point itself, approximating it would let the
+                    // source pull on itself however large theta is
+                    let distance = node.center.subtract(&point).length();
+                    if !node.contains(point) && distance > 0. && node.size / distance < theta {
+                        field = field.add(&contribution(node.center, node.weight));
+                    } else {
+                        stack.extend_from_slice(&children);
+                    }
+                }
+                None => {
+                    for source in node.sources.iter().filter(|source| **source != skip) {
+                        field = field.add(&contribution(self.positions[*source], self.weights[*source]));
+                    }
+                }
+            }
+        }
+        field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct_sum(positions: &[Vec2f], weights: &[f32], index: usize, softening: f32) -> Vec2f {
+        let mut field = Vec2f::new(0., 0.);
+        for (_, (position, weight)) in positions.iter().zip(weights).enumerate().filter(|(other, _)| *other != index) {
+            let delta = position.subtract(&positions[index]);
+            let distance_squared = delta.dot(&delta) + softening * softening;
+            field = field.add(&delta.scale(weight / (distance_squared * distance_squared.sqrt())));
+        }
+        field
+    }
+
+    #[test]
+    fn a_source_never_pulls_on_itself() {
+        // with a huge theta every node that does not contain the point is approximated
+        let positions = [Vec2f::new(0., 0.), Vec2f::new(10., 0.)];
+        let weights = [5., 1.];
+        let tree = QuadTree::build(&positions, &weights);
+        for index in 0..2 {
+            let field = tree.field_at(positions[index], index, 100., 0.);
+            let expected = direct_sum(&positions, &weights, index, 0.);
+            assert!(field.subtract(&expected).length() < 1e-6, "{:?} != {:?}", field, expected);
+        }
+    }
+
+    #[test]
+    fn matches_the_direct_sum() {
+        // a lopsided cloud with a dense clump, so the tree has both deep and shallow branches
+        let positions: Vec<Vec2f> = (0..64)
+            .map(|i| {
+                let angle = i as f32 * 2.4;
+                let radius = if i % 4 == 0 { 2. } else { 10. + i as f32 };
+                Vec2f::new(radius * angle.cos(), radius * angle.sin())
+            })
+            .collect();
+        let weights: Vec<f32> = (0..64).map(|i| 1. + (i % 3) as f32).collect();
+        let tree = QuadTree::build(&positions, &weights);
+        for index in 0..positions.len() {
+            let expected = direct_sum(&positions, &weights, index, 1.);
+            let exact = tree.field_at(positions[index], index, 0., 1.);
+            assert!(exact.subtract(&expected).length() <= 1e-4 * expected.length().max(1.), "theta 0 at {}: {:?} != {:?}", index, exact, expected);
+            let approximate = tree.field_at(positions[index], index, 0.5, 1.);
+            assert!(approximate.subtract(&expected).length() <= 0.05 * expected.length(), "theta 0.5 at {}: {:?} != {:?}", index, approximate, expected);
+        }
+    }
+}
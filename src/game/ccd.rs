@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use crate::game::obstacle::Obstacle;
+use crate::game::particle::Particle;
+use crate::game::species::SpeciesTable;
+use crate::math::{Vec2i, Vec2f};
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CcdMode {
+    // only particles flagged with Particle::ccd are swept
+    Off,
+    // every fast particle is swept against the walls and obstacles
+    Static,
+    // every fast particle is also swept against the other particles
+    All,
+}
+
+// bounces followed within one step before the rest of the motion is dropped
+const MAX_BOUNCES: usize = 4;
+// a particle moving less than this fraction of its radius in a step cannot tunnel through anything, so it is not swept
+const SWEEP_THRESHOLD: f32 = 0.5;
+
+// Earliest fraction of `motion` at which a circle starting at `start` touches the circle of `radius` around `center`.
+// Circles that already touch hit straight away while they move closer, the discrete solvers remove any overlap.
+fn circle_time_of_impact(start: Vec2f, motion: Vec2f, center: Vec2f, radius: f32) -> Option<f32> {
+    let offset = start.subtract(&center);
+    let c = offset.dot(&offset) - radius * radius;
+    let a = motion.dot(&motion);
+    let b = offset.dot(&motion);
+    if a <= f32::EPSILON || b >= 0. {
+        return None;
+    }
+    if c <= 0. {
+        return Some(0.);
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0. {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    (t <= 1.).then_some(t.max(0.))
+}
+
+// earliest fraction of `motion` at which the circle reaches one of the walls, with the wall's inward normal
+fn wall_time_of_impact(start: Vec2f, motion: Vec2f, bounds: Vec2i, radius: f32) -> Option<(f32, Vec2f)> {
+    let min = Vec2f::new(radius, radius);
+    let max = Vec2f::new(bounds.x as f32 - radius, bounds.y as f32 - radius);
+    let candidates = [
+        (motion.x < 0. && start.x >= min.x, (min.x - start.x) / motion.x, Vec2f::new(1., 0.)),
+        (motion.x > 0. && start.x <= max.x, (max.x - start.x) / motion.x, Vec2f::new(-1., 0.)),
+        (motion.y < 0. && start.y >= min.y, (min.y - start.y) / motion.y, Vec2f::new(0., 1.)),
+        (motion.y > 0. && start.y <= max.y, (max.y - start.y) / motion.y, Vec2f::new(0., -1.)),
+    ];
+    candidates.iter()
+        .filter(|(moving_towards, t, _)| *moving_towards && *t <= 1.)
+        .map(|(_, t, normal)| (*t, *normal))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+enum Impact {
+    // wall or obstacle, bounce off it with this normal
+    Static(Vec2f),
+    // another particle, share the normal velocity with it like two equal masses
+    Particle(usize, Vec2f),
+}
+
+// Integrates the swept particles over `dt` in place of World::apply_velocity: they stop at the first thing their path
+// crosses instead of jumping over it, and bounce off walls and obstacles with the wall damping and off other particles
+// with the collision damping. Returns which particles were moved here and the sleeping particles that were hit, which
+// took their share of the impulse and have to be woken to keep it. `reach` gives the partition reach for a search distance.
+#[allow(clippy::too_many_arguments)]
+pub fn sweep(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: impl Fn(f32) -> (i32, i32), species: &SpeciesTable,
+             bounds: Vec2i, obstacles: &[Obstacle], mode: CcdMode, wall_damping: f32, collision_damping: f32, friction: f32, dt: f32) -> (Vec<bool>, Vec<usize>) {
+    let mut swept = vec![false; particles.len()];
+    let mut woken = Vec::new();
+    for index in 0..particles.len() {
+        let particle = &particles[index];
+        let radius = species.radius(particle.species);
+        if particle.asleep || (mode == CcdMode::Off && !particle.ccd) || particle.velocity.length() * dt < radius * SWEEP_THRESHOLD {
+            continue;
+        }
+        let against_particles = mode == CcdMode::All || particle.ccd;
+        let mut position = particle.world_position;
+        let mut velocity = particle.velocity;
+        let mut remaining = 1.0;
+
+        for _ in 0..MAX_BOUNCES {
+            let motion = velocity.scale(dt * remaining);
+            let mut earliest: Option<(f32, Impact)> = None;
+            let mut consider = |t: f32, impact: Impact| {
+                if earliest.as_ref().is_none_or(|(best, _)| t < *best) {
+                    earliest = Some((t, impact));
+                }
+            };
+
+            if let Some((t, normal)) = wall_time_of_impact(position, motion, bounds, radius) {
+                consider(t, Impact::Static(normal));
+            }
+            for obstacle in obstacles.iter().filter(|obstacle| obstacle.radius > 0.) {
+                if let Some(t) = circle_time_of_impact(position, motion, obstacle.position, obstacle.radius + radius) {
+                    let contact = position.add(&motion.scale(t));
+                    consider(t, Impact::Static(contact.subtract(&obstacle.position).normalized()));
+                }
+            }
+            if against_particles {
+                // other particles are treated as standing still for the length of the step
+                let (reach_x, reach_y) = reach(motion.length() + radius + species.max_radius());
+                let (cell_x, cell_y) = particles[index].current_spatial_partition;
+                for dx in -reach_x..=reach_x {
+                    for dy in -reach_y..=reach_y {
+                        let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                        for other in cell.iter().map(|other| *other as usize).filter(|other| *other != index) {
+                            if particles[index].shares_rigid_body(&particles[other]) {
+                                continue;
+                            }
+                            let contact_distance = radius + species.radius(particles[other].species);
+                            if let Some(t) = circle_time_of_impact(position, motion, particles[other].world_position, contact_distance) {
+                                let contact = position.add(&motion.scale(t));
+                                let delta = contact.subtract(&particles[other].world_position);
+                                let normal = if delta.length() > f32::EPSILON { delta.normalized() } else { motion.normalized().scale(-1.) };
+                                consider(t, Impact::Particle(other, normal));
+                            }
+                        }
+                    }
+                }
+            }
+
+            match earliest {
+                None => {
+                    position = position.add(&motion);
+                    break;
+                }
+                Some((t, Impact::Particle(other, normal))) => {
+                    position = position.add(&motion.scale(t));
+                    let normal_speed = velocity.subtract(&particles[other].velocity).dot(&normal);
+                    if normal_speed < 0. {
+                        let impulse = normal.scale((1.0 + collision_damping) * normal_speed / 2.0);
+                        velocity = velocity.subtract(&impulse);
+                        particles[other].velocity = particles[other].velocity.add(&impulse);
+                        if particles[other].asleep {
+                            woken.push(other);
+                        }
+                    }
+                    remaining *= 1.0 - t;
+                }
+                Some((t, Impact::Static(normal))) => {
+                    position = position.add(&motion.scale(t));
+                    let normal_speed = velocity.dot(&normal);
+                    if normal_speed < 0. {
+                        velocity = velocity.subtract(&normal.scale((1.0 + wall_damping) * normal_speed));
+                    }
+                    remaining *= 1.0 - t;
+                }
+            }
+        }
+
+        let particle = &mut particles[index];
+        particle.world_position = position;
+        particle.velocity = velocity.scale(friction);
+        swept[index] = true;
+    }
+    (swept, woken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec4f;
+
+    #[test]
+    fn reports_sleeping_particles_it_hits() {
+        let species = SpeciesTable::new(Vec4f::new(1., 1., 1., 1.), 5.);
+        let mut sleeper = Particle::new(Vec2f::new(50., 50.), Vec2f::new(0., 0.), (0, 0));
+        sleeper.asleep = true;
+        let projectile = Particle::new(Vec2f::new(20., 50.), Vec2f::new(5000., 0.), (0, 0));
+        let mut particles = vec![sleeper, projectile];
+        let spatial_partition = HashMap::from([((0, 0), vec![0, 1])]);
+
+        let (swept, woken) = sweep(&mut particles, &spatial_partition, |_| (1, 1), &species, Vec2i{ x: 100, y: 100 }, &[], CcdMode::All, 1., 1., 1., 0.01);
+        assert_eq!(swept, vec![false, true]);
+        assert_eq!(woken, vec![0]);
+        // the sleeper holds the impulse until it is woken
+        assert!(particles[0].velocity.x > 0.);
+        assert!(particles[1].world_position.x <= 40. + 1e-3);
+    }
+}
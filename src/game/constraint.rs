@@ -0,0 +1,144 @@
+use crate::game::particle::Particle;
+use crate::math::Vec2f;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstraintKind {
+    // Hookean spring, pulls the pair towards the rest length with a force instead of correcting positions
+    Spring { stiffness: f32, damping: f32 },
+    // Rigid link, the pair is projected back to exactly the rest length
+    Distance,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraint {
+    pub a: usize,
+    pub b: usize,
+    pub rest_length: f32,
+    pub kind: ConstraintKind,
+    pub break_strain: Option<f32>, //link is removed once |length - rest_length| / rest_length exceeds this
+}
+
+impl Constraint {
+    pub fn new(a: usize, b: usize, rest_length: f32, kind: ConstraintKind, break_strain: Option<f32>) -> Self {
+        Constraint { a, b, rest_length, kind, break_strain }
+    }
+
+    pub fn strain(&self, particles: &[Particle]) -> f32 {
+        let length = particles[self.a].world_position.subtract(&particles[self.b].world_position).length();
+        if self.rest_length <= 0. {
+            return 0.;
+        }
+        (length - self.rest_length).abs() / self.rest_length
+    }
+
+    pub fn is_broken(&self, particles: &[Particle]) -> bool {
+        self.break_strain.is_some_and(|threshold| self.strain(particles) > threshold)
+    }
+
+    // direction from b to a and the current length, None when the two particles coincide
+    fn axis(&self, particles: &[Particle]) -> Option<(Vec2f, f32)> {
+        let delta = particles[self.a].world_position.subtract(&particles[self.b].world_position);
+        let length = delta.length();
+        if length <= f32::EPSILON {
+            return None;
+        }
+        Some((delta.scale(1. / length), length))
+    }
+
+    pub fn apply_spring(&self, particles: &mut [Particle], dt: f32) {
+        let ConstraintKind::Spring { stiffness, damping } = self.kind else { return };
+        let Some((direction, length)) = self.axis(particles) else { return };
+
+        let relative_velocity = particles[self.a].velocity.subtract(&particles[self.b].velocity);
+        let closing_speed = relative_velocity.dot(&direction);
+        let force = -stiffness * (length - self.rest_length) - damping * closing_speed;
+        let impulse = direction.scale(force * dt);
+
+        particles[self.a].velocity = particles[self.a].velocity.add(&impulse);
+        particles[self.b].velocity = particles[self.b].velocity.subtract(&impulse);
+    }
+
+    pub fn solve_distance(&self, particles: &mut [Particle]) {
+        if self.kind != ConstraintKind::Distance {
+            return;
+        }
+        let Some((direction, length)) = self.axis(particles) else { return };
+
+        let correction = direction.scale((length - self.rest_length) / 2.0);
+        particles[self.a].world_position = particles[self.a].world_position.subtract(&correction);
+        particles[self.b].world_position = particles[self.b].world_position.add(&correction);
+
+        // remove the relative velocity along the link so the pair does not keep drifting apart
+        let relative_velocity = particles[self.a].velocity.subtract(&particles[self.b].velocity);
+        let closing_speed = relative_velocity.dot(&direction);
+        let velocity_correction = direction.scale(closing_speed / 2.0);
+        particles[self.a].velocity = particles[self.a].velocity.subtract(&velocity_correction);
+        particles[self.b].velocity = particles[self.b].velocity.add(&velocity_correction);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_state::ConfigState;
+    use crate::game::world::World;
+
+    fn pair(distance: f32) -> Vec<Particle> {
+        vec![Particle::new(Vec2f::new(0., 0.), Vec2f::new(0., 0.), (0, 0)), Particle::new(Vec2f::new(distance, 0.), Vec2f::new(0., 0.), (0, 0))]
+    }
+
+    #[test]
+    fn springs_push_towards_the_rest_length() {
+        let spring = Constraint::new(0, 1, 10., ConstraintKind::Spring { stiffness: 2., damping: 0. }, None);
+        let mut stretched = pair(12.);
+        spring.apply_spring(&mut stretched, 0.5);
+        assert_eq!((stretched[0].velocity.x, stretched[1].velocity.x), (2., -2.));
+
+        let mut compressed = pair(7.);
+        spring.apply_spring(&mut compressed, 0.5);
+        assert_eq!((compressed[0].velocity.x, compressed[1].velocity.x), (-3., 3.));
+
+        let mut resting = pair(10.);
+        spring.apply_spring(&mut resting, 0.5);
+        assert_eq!((resting[0].velocity.x, resting[1].velocity.x), (0., 0.));
+    }
+
+    #[test]
+    fn distance_links_snap_back_to_the_rest_length() {
+        let link = Constraint::new(0, 1, 10., ConstraintKind::Distance, None);
+        let mut particles = pair(16.);
+        particles[1].velocity = Vec2f::new(4., 1.);
+        link.solve_distance(&mut particles);
+        assert_eq!(particles[0].world_position.x, 3.);
+        assert_eq!(particles[1].world_position.x, 13.);
+        // the separating speed along the link is gone, the sideways part is left alone
+        assert_eq!(particles[0].velocity, Vec2f::new(2., 0.));
+        assert_eq!(particles[1].velocity, Vec2f::new(2., 1.));
+    }
+
+    #[test]
+    fn links_break_past_their_strain() {
+        let link = Constraint::new(0, 1, 10., ConstraintKind::Distance, Some(0.25));
+        assert!((link.strain(&pair(12.)) - 0.2).abs() < 1e-6);
+        assert!(!link.is_broken(&pair(12.)));
+        assert!(link.is_broken(&pair(13.)));
+        assert!(link.is_broken(&pair(7.)));
+        assert!(!Constraint::new(0, 1, 10., ConstraintKind::Distance, None).is_broken(&pair(100.)));
+    }
+
+    #[test]
+    fn world_links_rest_at_their_spawn_distance_and_break_in_a_step() {
+        let mut world = World::new(ConfigState::new());
+        let a = world.spawn_particle(Vec2f::new(400., 400.), Vec2f::new(0., 0.));
+        let b = world.spawn_particle(Vec2f::new(420., 400.), Vec2f::new(0., 0.));
+        assert!(world.add_spring(a, b, 50., 1., Some(0.5)));
+        assert!(!world.add_spring(a, a, 50., 1., None));
+        assert_eq!(world.get_constraints()[0].rest_length, 20.);
+
+        world.step(0.01);
+        assert_eq!(world.get_constraints().len(), 1);
+        world.particles[b].world_position = Vec2f::new(435., 400.);
+        world.step(0.01);
+        assert!(world.get_constraints().is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+use crate::game::barnes_hut::QuadTree;
+use crate::game::obstacle::Obstacle;
+use crate::game::particle::Particle;
+use crate::math::Vec2f;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CoulombMode {
+    Off,
+    // exact pairwise force between particles closer than the cutoff, found through the spatial partitions
+    Cutoff,
+    // every pair, approximated with the Barnes-Hut tree
+    BarnesHut,
+}
+
+pub struct CoulombParameters {
+    pub mode: CoulombMode,
+    pub constant: f32,
+    pub cutoff: f32,
+    pub softening: f32,
+    pub theta: f32,
+}
+
+// acceleration of a unit mass charge `charge` at `delta` from a source charge, like charges repel
+fn coulomb(delta: Vec2f, charge: f32, source_charge: f32, constant: f32, softening: f32) -> Vec2f {
+    let distance_squared = delta.dot(&delta) + softening * softening;
+    if distance_squared <= f32::EPSILON {
+        return Vec2f::new(0., 0.);
+    }
+    delta.scale(constant * charge * source_charge / (distance_squared * distance_squared.sqrt()))
+}
+
+pub fn apply_coulomb(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), parameters: &CoulombParameters, dt: f32) {
+    let accelerations: Vec<Vec2f> = match parameters.mode {
+        CoulombMode::Off => return,
+        CoulombMode::Cutoff => (0..particles.len()).map(|index| {
+            let particle = &particles[index];
+            let mut acceleration = Vec2f::new(0., 0.);
+            if particle.charge == 0. {
+                return acceleration;
+            }
+            let (cell_x, cell_y) = particle.current_spatial_partition;
+            for dx in -reach.0..=reach.0 {
+                for dy in -reach.1..=reach.1 {
+                    let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                    for neighbor in cell.iter().filter(|neighbor| **neighbor as usize != index) {
+                        let other = &particles[*neighbor as usize];
+                        let delta = particle.world_position.subtract(&other.world_position);
+                        if other.charge != 0. && delta.length() < parameters.cutoff {
+                            acceleration = acceleration.add(&coulomb(delta, particle.charge, other.charge, parameters.constant, parameters.softening));
+                        }
+                    }
+                }
+            }
+            acceleration
+        }).collect(),
+        CoulombMode::BarnesHut => {
+            let positions: Vec<Vec2f> = particles.iter().map(|particle| particle.world_position).collect();
+            let charges: Vec<f32> = particles.iter().map(|particle| particle.charge).collect();
+            let tree = QuadTree::build(&positions, &charges);
+            // the tree field points towards the sources, so flip it for repulsion between like charges
+            (0..particles.len()).map(|index| {
+                if charges[index] == 0. {
+                    return Vec2f::new(0., 0.);
+                }
+                let field = tree.field_at(positions[index], index, parameters.theta, parameters.softening);
+                field.scale(-parameters.constant * charges[index])
+            }).collect()
+        }
+    };
+
+    // sleeping charges are still sources, they just do not move
+    for (particle, acceleration) in particles.iter_mut().zip(accelerations).filter(|(particle, _)| !particle.asleep) {
+        particle.velocity = particle.velocity.add(&acceleration.scale(dt));
+    }
+}
+
+// fixed charged obstacles and the uniform field act on every charged particle regardless of the mode
+pub fn apply_external_fields(particles: &mut [Particle], obstacles: &[Obstacle], electric_field: Vec2f, parameters: &CoulombParameters, dt: f32) {
+    for particle in particles.iter_mut().filter(|particle| particle.charge != 0. && !particle.asleep) {
+        let mut acceleration = electric_field.scale(particle.charge);
+        for obstacle in obstacles.iter().filter(|obstacle| obstacle.charge != 0.) {
+            let delta = particle.world_position.subtract(&obstacle.position);
+            acceleration = acceleration.add(&coulomb(delta, particle.charge, obstacle.charge, parameters.constant, parameters.softening));
+        }
+        particle.velocity = particle.velocity.add(&acceleration.scale(dt));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parameters(mode: CoulombMode) -> CoulombParameters {
+        CoulombParameters { mode, constant: 1000., cutoff: 50., softening: 0., theta: 0.5 }
+    }
+
+    // x velocities after one step of 0.1, for two charges `distance` apart along x
+    fn pair_velocities(mode: CoulombMode, charge_a: f32, charge_b: f32, distance: f32) -> (f32, f32) {
+        let mut particles = vec![Particle::new(Vec2f::new(100., 100.), Vec2f::new(0., 0.), (0, 0)), Particle::new(Vec2f::new(100. + distance, 100.), Vec2f::new(0., 0.), (0, 0))];
+        particles[0].charge = charge_a;
+        particles[1].charge = charge_b;
+        let spatial_partition = HashMap::from([((0, 0), vec![0, 1])]);
+        apply_coulomb(&mut particles, &spatial_partition, (1, 1), &parameters(mode), 0.1);
+        (particles[0].velocity.x, particles[1].velocity.x)
+    }
+
+    fn assert_near(actual: (f32, f32), expected: (f32, f32)) {
+        assert!((actual.0 - expected.0).abs() < 1e-4 && (actual.1 - expected.1).abs() < 1e-4, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn like_charges_repel_and_unlike_attract() {
+        for mode in [CoulombMode::Cutoff, CoulombMode::BarnesHut] {
+            // k q q / r^2 = 1000 / 100, for 0.1 seconds
+            assert_near(pair_velocities(mode, 1., 1., 10.), (-1., 1.));
+            assert_near(pair_velocities(mode, -1., -1., 10.), (-1., 1.));
+            assert_near(pair_velocities(mode, 1., -2., 10.), (2., -2.));
+            assert_near(pair_velocities(mode, 1., 0., 10.), (0., 0.));
+        }
+        assert_near(pair_velocities(CoulombMode::Off, 1., 1., 10.), (0., 0.));
+    }
+
+    #[test]
+    fn cutoff_ignores_distant_pairs() {
+        assert_near(pair_velocities(CoulombMode::Cutoff, 1., 1., 60.), (0., 0.));
+        assert!(pair_velocities(CoulombMode::BarnesHut, 1., 1., 60.).1 > 0.);
+    }
+
+    #[test]
+    fn obstacles_and_the_field_push_by_sign() {
+        let mut particles = vec![Particle::new(Vec2f::new(110., 100.), Vec2f::new(0., 0.), (0, 0)), Particle::new(Vec2f::new(90., 100.), Vec2f::new(0., 0.), (0, 0))];
+        particles[0].charge = 1.;
+        particles[1].charge = -1.;
+        let obstacles = [Obstacle::new(Vec2f::new(100., 100.), 5., 1.)];
+        apply_external_fields(&mut particles, &obstacles, Vec2f::new(0., 5.), &parameters(CoulombMode::Off), 0.1);
+        // the like charge is pushed away from the obstacle, the other pulled in, and the field splits them vertically
+        assert_eq!(particles[0].velocity, Vec2f::new(1., 0.5));
+        assert_eq!(particles[1].velocity, Vec2f::new(1., -0.5));
+    }
+}
@@ -0,0 +1,204 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+pub const DEFAULT_FIXED_TIMESTEP: f32 = 0.01;
+pub const DEFAULT_MAX_CATCH_UP_STEPS: u32 = 10;
+pub const DEFAULT_SPEED: f32 = 1.0;
+
+// Fixed timestep loop driven by requestAnimationFrame. Real time is collected in an accumulator and spent in whole
+// fixed physics steps, rendering happens once per display frame with whatever is left over as the interpolation factor.
+pub struct GameLoop {
+    pub fixed_timestep: f32,
+    pub max_catch_up_steps: u32, //physics steps a single frame may take before the rest of the backlog is dropped
+    pub speed: f32, //scale of simulated time against real time
+    paused: bool,
+    pending_steps: u32, //single steps requested while paused
+    accumulator: f32,
+    last_timestamp: Option<f64>,
+    animation_frame: Option<i32>,
+    callback: Option<Closure<dyn FnMut(f64)>>,
+}
+
+impl GameLoop {
+    pub fn new() -> Self {
+        GameLoop {
+            fixed_timestep: DEFAULT_FIXED_TIMESTEP,
+            max_catch_up_steps: DEFAULT_MAX_CATCH_UP_STEPS,
+            speed: DEFAULT_SPEED,
+            paused: false,
+            pending_steps: 0,
+            accumulator: 0.,
+            last_timestamp: None,
+            animation_frame: None,
+            callback: None,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.callback.is_some()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    // advance exactly one fixed step on the next frame, only while paused
+    pub fn request_step(&mut self) {
+        if self.paused {
+            self.pending_steps += 1;
+        }
+    }
+
+    // how far the accumulator is into the next fixed step, 0 is the last physics state and 1 the next one
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_timestep).clamp(0., 1.)
+    }
+
+    // number of fixed steps to take for the frame at `timestamp`, in milliseconds
+    fn advance(&mut self, timestamp: f64) -> u32 {
+        let elapsed = self.last_timestamp.map_or(0., |last| ((timestamp - last) / 1000.) as f32).max(0.);
+        self.last_timestamp = Some(timestamp);
+        if self.paused {
+            return std::mem::take(&mut self.pending_steps);
+        }
+
+        self.accumulator += elapsed * self.speed;
+        let mut steps = 0;
+        while self.accumulator >= self.fixed_timestep && steps < self.max_catch_up_steps {
+            self.accumulator -= self.fixed_timestep;
+            steps += 1;
+        }
+        // a frame that fell too far behind drops the backlog instead of snowballing into ever longer frames
+        if self.accumulator >= self.fixed_timestep {
+            self.accumulator %= self.fixed_timestep;
+        }
+        steps
+    }
+}
+
+impl Default for GameLoop {
+    fn default() -> Self {
+        GameLoop::new()
+    }
+}
+
+impl Drop for GameLoop {
+    // the browser must not call back into a closure that is about to be freed
+    fn drop(&mut self) {
+        if let Some(handle) = self.animation_frame.take() {
+            if let Some(window) = web_sys::window() {
+                let _ = window.cancel_animation_frame(handle);
+            }
+        }
+    }
+}
+
+// Starts requesting frames, each frame calls `step` once per fixed step that is due and then `render` with the
+// interpolation factor. The closure only holds a weak reference to the loop, so dropping the loop ends it.
+pub fn start(game_loop: &Rc<RefCell<GameLoop>>, mut step: impl FnMut(f32) + 'static, mut render: impl FnMut(f32) + 'static) {
+    if game_loop.borrow().is_running() {
+        return;
+    }
+    let weak_loop = Rc::downgrade(game_loop);
+    let callback = Closure::<dyn FnMut(f64)>::new(move |timestamp: f64| {
+        let Some(game_loop) = weak_loop.upgrade() else { return };
+        let (steps, dt) = {
+            let mut game_loop = game_loop.borrow_mut();
+            game_loop.animation_frame = None;
+            (game_loop.advance(timestamp), game_loop.fixed_timestep)
+        };
+        for _ in 0..steps {
+            step(dt);
+        }
+        let alpha = game_loop.borrow().alpha();
+        render(alpha);
+        request_frame(&game_loop);
+    });
+
+    let mut state = game_loop.borrow_mut();
+    state.callback = Some(callback);
+    state.last_timestamp = None;
+    drop(state);
+    request_frame(game_loop);
+}
+
+pub fn stop(game_loop: &Rc<RefCell<GameLoop>>) {
+    let mut game_loop = game_loop.borrow_mut();
+    if let Some(handle) = game_loop.animation_frame.take() {
+        if let Some(window) = web_sys::window() {
+            let _ = window.cancel_animation_frame(handle);
+        }
+    }
+    game_loop.callback = None;
+}
+
+fn request_frame(game_loop: &Rc<RefCell<GameLoop>>) {
+    let mut game_loop = game_loop.borrow_mut();
+    let Some(window) = web_sys::window() else { return };
+    let Some(callback) = &game_loop.callback else { return };
+    let handle = window.request_animation_frame(callback.as_ref().unchecked_ref()).ok();
+    game_loop.animation_frame = handle;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a quarter second step, exact in binary so whole frames leave nothing behind in the accumulator
+    fn quarter_second_loop() -> GameLoop {
+        let mut game_loop = GameLoop::new();
+        game_loop.fixed_timestep = 0.25;
+        game_loop.advance(0.);
+        game_loop
+    }
+
+    #[test]
+    fn takes_the_steps_that_are_due_and_keeps_the_remainder() {
+        let mut game_loop = quarter_second_loop();
+        assert_eq!(game_loop.advance(625.), 2);
+        assert_eq!(game_loop.alpha(), 0.5);
+        assert_eq!(game_loop.advance(750.), 1);
+        assert_eq!(game_loop.alpha(), 0.);
+    }
+
+    #[test]
+    fn drops_the_backlog_after_a_long_frame() {
+        let mut game_loop = quarter_second_loop();
+        game_loop.max_catch_up_steps = 4;
+        // ten seconds behind, only four steps are taken and the rest is forgotten
+        assert_eq!(game_loop.advance(10_125.), 4);
+        assert_eq!(game_loop.alpha(), 0.5);
+        assert_eq!(game_loop.advance(10_250.), 1);
+    }
+
+    #[test]
+    fn steps_only_on_request_while_paused() {
+        let mut game_loop = quarter_second_loop();
+        game_loop.set_paused(true);
+        assert_eq!(game_loop.advance(1000.), 0);
+        game_loop.request_step();
+        game_loop.request_step();
+        assert_eq!(game_loop.advance(1001.), 2);
+        assert_eq!(game_loop.advance(1002.), 0);
+        // requests made while running are ignored, time spent paused is not caught up on
+        game_loop.set_paused(false);
+        game_loop.request_step();
+        assert_eq!(game_loop.advance(1252.), 1);
+    }
+
+    #[test]
+    fn speed_scales_simulated_time() {
+        let mut game_loop = quarter_second_loop();
+        game_loop.speed = 2.;
+        assert_eq!(game_loop.advance(500.), 4);
+        game_loop.speed = 0.5;
+        assert_eq!(game_loop.advance(1000.), 1);
+    }
+}
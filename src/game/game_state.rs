@@ -1,14 +1,49 @@
 use wasm_bindgen::prelude::*;
 use crate::{renderer::Context, util};
+use crate::util::Error;
+use crate::renderer::circle_buffer::InstanceData;
+use crate::renderer::{Camera2D, CanvasBackend, LineBuffer, RenderBackend};
+use crate::renderer::display_size::{device_pixel_ratio, to_device_pixels};
 use crate::game::world::*;
+use crate::game::stats::WorldStats;
+use crate::util::profiler::PhaseTimings;
+use crate::game::sph::{EquationOfState, SphParameters};
+use crate::game::species::SpeciesTable;
+use crate::game::electrostatics::{CoulombMode, CoulombParameters};
+use crate::game::thermal::{self, ThermalWall, ThermostatMode, WallSide};
+use crate::game::ccd::CcdMode;
+use crate::game::game_loop::{self, GameLoop};
 use console_error_panic_hook;
 use std::panic;
-use crate::math::{Vec2i, Vec2f};
+use std::cell::{RefCell, RefMut};
+use std::rc::Rc;
+use crate::math::{Vec2i, Vec2f, Vec4f};
+
+// Everything a frame touches. Shared between GameState and the frame callback of the game loop.
+pub(crate) struct Scene<B: RenderBackend = CanvasBackend> {
+    pub(crate) render_context: Context<B>,
+    pub(crate) physics_simulation: World,
+    color_by_temperature: bool,
+    temperature_range: (f32, f32), //cold and hot ends of the temperature colors
+    follow_particle: Option<usize>, //the camera stays centered on this particle
+}
 
 #[wasm_bindgen]
 pub struct GameState {
-    render_context: Context,
-    physics_simulation: World,
+    scene: Rc<RefCell<Scene>>,
+    game_loop: Rc<RefCell<GameLoop>>,
+    wheel_zoom_speed: f32,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolverMode {
+    // rigid grains pushed apart by World::solve_overlap
+    Granular,
+    // smoothed particle hydrodynamics fluid
+    Sph,
+    // extended position based dynamics, contacts, links and walls as compliant constraints
+    Xpbd,
 }
 
 pub struct ConfigState {
@@ -22,6 +57,36 @@ pub struct ConfigState {
     pub friction: f32,
     pub time_multiplier: f32,
     pub spatial_partition_size: f32,
+    pub constraint_iterations: usize,
+    pub soft_body_stiffness: f32,
+    pub soft_body_damping: f32,
+    pub soft_body_pressure: f32,
+    pub solver_mode: SolverMode,
+    pub sph: SphParameters,
+    pub xpbd_substeps: usize,
+    pub xpbd_contact_compliance: f32,
+    pub xpbd_boundary_compliance: f32,
+    pub species: SpeciesTable,
+    pub mutual_gravity: bool,
+    pub gravitational_constant: f32,
+    pub barnes_hut_theta: f32,
+    pub gravity_softening: f32,
+    pub coulomb: CoulombParameters,
+    pub electric_field: Vec2f,
+    pub ambient_temperature: f32,
+    pub heat_conductivity: f32,
+    pub thermal_expansion: f32,
+    pub thermostat: ThermostatMode,
+    pub thermostat_target: f32,
+    pub thermostat_tau: f32,
+    pub sleeping: bool,
+    pub sleep_speed: f32,
+    pub sleep_steps: u32,
+    pub ccd: CcdMode,
+    pub adaptive_timestep: bool,
+    pub cfl_number: f32,
+    pub max_adaptive_steps: usize,
+    pub max_frame_time: f32,
 }
 pub const DEFAULT_BOUNDS: Vec2i = Vec2i{ x: 800, y: 800 };
 pub const DEFAULT_MAX_PARTICLES: usize = 1500;
@@ -33,9 +98,61 @@ pub const DEFAULT_COLLISION_DAMPING: f32 = 0.9;
 pub const DEFAULT_FRICTION: f32 = 0.999;
 pub const DEFAULT_TIME_MULTIPLIER: f32 = 3.0;
 pub const DEFAULT_SPATIAL_PARTITION_SIZE: f32 = 100.;
+pub const DEFAULT_CONSTRAINT_ITERATIONS: usize = 4;
+pub const DEFAULT_SOFT_BODY_STIFFNESS: f32 = 50.;
+pub const DEFAULT_SOFT_BODY_DAMPING: f32 = 1.;
+pub const DEFAULT_SOFT_BODY_PRESSURE: f32 = 20.;
+pub const DEFAULT_SOLVER_MODE: SolverMode = SolverMode::Granular;
+pub const DEFAULT_SPH_SMOOTHING_RADIUS: f32 = 4. * DEFAULT_PARTICLE_RADIUS;
+pub const DEFAULT_SPH_REST_DENSITY: f32 = 0.008;
+pub const DEFAULT_SPH_STIFFNESS: f32 = 30.;
+pub const DEFAULT_SPH_VISCOSITY: f32 = 2.;
+pub const DEFAULT_SPH_SURFACE_TENSION: f32 = 20.;
+pub const DEFAULT_SPH_EQUATION_OF_STATE: EquationOfState = EquationOfState::Tait;
+pub const DEFAULT_XPBD_SUBSTEPS: usize = 8;
+pub const DEFAULT_XPBD_CONTACT_COMPLIANCE: f32 = 0.;
+pub const DEFAULT_XPBD_BOUNDARY_COMPLIANCE: f32 = 0.;
+pub const DEFAULT_SPECIES_COLOR: Vec4f = Vec4f{ x: 0.5, y: 0.7, z: 0.4, w: 1.0 };
+pub const DEFAULT_MUTUAL_GRAVITY: bool = false;
+pub const DEFAULT_GRAVITATIONAL_CONSTANT: f32 = 100.;
+pub const DEFAULT_BARNES_HUT_THETA: f32 = 0.5;
+pub const DEFAULT_GRAVITY_SOFTENING: f32 = DEFAULT_PARTICLE_RADIUS;
+pub const DEFAULT_COULOMB_MODE: CoulombMode = CoulombMode::Off;
+pub const DEFAULT_COULOMB_CONSTANT: f32 = 1000.;
+pub const DEFAULT_COULOMB_CUTOFF: f32 = 50.;
+pub const DEFAULT_COULOMB_SOFTENING: f32 = DEFAULT_PARTICLE_RADIUS;
+pub const DEFAULT_ELECTRIC_FIELD: Vec2f = Vec2f{ x: 0.0, y: 0.0 };
+pub const DEFAULT_AMBIENT_TEMPERATURE: f32 = 0.;
+pub const DEFAULT_HEAT_CONDUCTIVITY: f32 = 1.5; //per second and unit of overlap
+pub const DEFAULT_THERMAL_EXPANSION: f32 = 0.;
+pub const DEFAULT_THERMOSTAT: ThermostatMode = ThermostatMode::Off;
+pub const DEFAULT_THERMOSTAT_TARGET: f32 = 1000.;
+pub const DEFAULT_THERMOSTAT_TAU: f32 = 1.;
+pub const DEFAULT_SLEEPING: bool = false;
+pub const DEFAULT_SLEEP_SPEED: f32 = 2.;
+pub const DEFAULT_SLEEP_STEPS: u32 = 60;
+pub const DEFAULT_CCD_MODE: CcdMode = CcdMode::Off;
+pub const DEFAULT_ADAPTIVE_TIMESTEP: bool = false;
+pub const DEFAULT_CFL_NUMBER: f32 = 0.5;
+pub const DEFAULT_MAX_ADAPTIVE_STEPS: usize = 8;
+pub const DEFAULT_MAX_FRAME_TIME: f32 = 0.1;
+pub const DEFAULT_TEMPERATURE_RANGE: (f32, f32) = (-1., 1.);
+pub const DEFAULT_WHEEL_ZOOM_SPEED: f32 = 0.002; //zoom factor exponent per pixel of wheel deltaY
+
+const OBSTACLE_OUTLINE_SEGMENTS: usize = 24;
+
+fn push_circle_outline(lines: &mut LineBuffer, center: Vec2f, radius: f32) {
+    let point = |i: usize| {
+        let angle = 2.0 * std::f32::consts::PI * (i as f32) / (OBSTACLE_OUTLINE_SEGMENTS as f32);
+        center.add(&Vec2f::new(radius * angle.cos(), radius * angle.sin()))
+    };
+    for i in 0..OBSTACLE_OUTLINE_SEGMENTS {
+        lines.push_line(point(i), point(i + 1));
+    }
+}
 
 impl ConfigState{
-    fn new() -> ConfigState{
+    pub(crate) fn new() -> ConfigState{
         let bounds = DEFAULT_BOUNDS;
         let max_particles = DEFAULT_MAX_PARTICLES;
         let particle_radius = DEFAULT_PARTICLE_RADIUS;
@@ -46,51 +163,629 @@ impl ConfigState{
         let friction = DEFAULT_FRICTION;
         let time_multiplier = DEFAULT_TIME_MULTIPLIER;
         let spatial_partition_size = DEFAULT_SPATIAL_PARTITION_SIZE;
-        return ConfigState{ bounds, max_particles, particle_radius, gravity_vector, wall_damping, repulsion_force, collision_damping, friction, time_multiplier, spatial_partition_size }
+        let constraint_iterations = DEFAULT_CONSTRAINT_ITERATIONS;
+        let soft_body_stiffness = DEFAULT_SOFT_BODY_STIFFNESS;
+        let soft_body_damping = DEFAULT_SOFT_BODY_DAMPING;
+        let soft_body_pressure = DEFAULT_SOFT_BODY_PRESSURE;
+        let solver_mode = DEFAULT_SOLVER_MODE;
+        let sph = SphParameters {
+            smoothing_radius: DEFAULT_SPH_SMOOTHING_RADIUS,
+            rest_density: DEFAULT_SPH_REST_DENSITY,
+            stiffness: DEFAULT_SPH_STIFFNESS,
+            viscosity: DEFAULT_SPH_VISCOSITY,
+            surface_tension: DEFAULT_SPH_SURFACE_TENSION,
+            equation_of_state: DEFAULT_SPH_EQUATION_OF_STATE,
+        };
+        let xpbd_substeps = DEFAULT_XPBD_SUBSTEPS;
+        let xpbd_contact_compliance = DEFAULT_XPBD_CONTACT_COMPLIANCE;
+        let xpbd_boundary_compliance = DEFAULT_XPBD_BOUNDARY_COMPLIANCE;
+        let species = SpeciesTable::new(DEFAULT_SPECIES_COLOR, particle_radius);
+        let mutual_gravity = DEFAULT_MUTUAL_GRAVITY;
+        let gravitational_constant = DEFAULT_GRAVITATIONAL_CONSTANT;
+        let barnes_hut_theta = DEFAULT_BARNES_HUT_THETA;
+        let gravity_softening = DEFAULT_GRAVITY_SOFTENING;
+        let coulomb = CoulombParameters {
+            mode: DEFAULT_COULOMB_MODE,
+            constant: DEFAULT_COULOMB_CONSTANT,
+            cutoff: DEFAULT_COULOMB_CUTOFF,
+            softening: DEFAULT_COULOMB_SOFTENING,
+            theta: DEFAULT_BARNES_HUT_THETA,
+        };
+        let electric_field = DEFAULT_ELECTRIC_FIELD;
+        let ambient_temperature = DEFAULT_AMBIENT_TEMPERATURE;
+        let heat_conductivity = DEFAULT_HEAT_CONDUCTIVITY;
+        let thermal_expansion = DEFAULT_THERMAL_EXPANSION;
+        let thermostat = DEFAULT_THERMOSTAT;
+        let thermostat_target = DEFAULT_THERMOSTAT_TARGET;
+        let thermostat_tau = DEFAULT_THERMOSTAT_TAU;
+        let sleeping = DEFAULT_SLEEPING;
+        let sleep_speed = DEFAULT_SLEEP_SPEED;
+        let sleep_steps = DEFAULT_SLEEP_STEPS;
+        let ccd = DEFAULT_CCD_MODE;
+        let adaptive_timestep = DEFAULT_ADAPTIVE_TIMESTEP;
+        let cfl_number = DEFAULT_CFL_NUMBER;
+        let max_adaptive_steps = DEFAULT_MAX_ADAPTIVE_STEPS;
+        let max_frame_time = DEFAULT_MAX_FRAME_TIME;
+        return ConfigState{ bounds, max_particles, particle_radius, gravity_vector, wall_damping, repulsion_force, collision_damping, friction, time_multiplier, spatial_partition_size, constraint_iterations,
+                            soft_body_stiffness, soft_body_damping, soft_body_pressure, solver_mode, sph, xpbd_substeps, xpbd_contact_compliance, xpbd_boundary_compliance, species,
+                            mutual_gravity, gravitational_constant, barnes_hut_theta, gravity_softening, coulomb, electric_field,
+                            ambient_temperature, heat_conductivity, thermal_expansion, thermostat, thermostat_target, thermostat_tau,
+                            sleeping, sleep_speed, sleep_steps, ccd,
+                            adaptive_timestep, cfl_number, max_adaptive_steps, max_frame_time }
     }
 }
 
 #[wasm_bindgen]
 impl GameState {
-    pub fn new(canvas_id: &str) -> GameState {
+    // fails, throwing on the JS side, when the canvas is missing or WebGL cannot be set up on it
+    pub fn new(canvas_id: &str) -> Result<GameState, Error> {
         panic::set_hook(Box::new(console_error_panic_hook::hook));
-        let render_context = Context::new(canvas_id).unwrap();
+        util::log::init();
+        let render_context = Context::new(canvas_id)?;
+        let scene = Scene::new(render_context);
+        Ok(GameState {
+            scene: Rc::new(RefCell::new(scene)),
+            game_loop: Rc::new(RefCell::new(GameLoop::new())),
+            wheel_zoom_speed: DEFAULT_WHEEL_ZOOM_SPEED,
+        })
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.world().step(dt);
+    }
+
+    // `alpha` blends from the state before the last update (0) to the current one (1)
+    pub fn render(&mut self, alpha: f32) {
+        self.scene.borrow_mut().render(alpha);
+    }
+
+    // drive update and render from requestAnimationFrame with a fixed timestep, instead of from JS
+    pub fn start(&mut self) {
+        let step_scene = self.scene.clone();
+        let render_scene = self.scene.clone();
+        game_loop::start(&self.game_loop, move |dt| step_scene.borrow_mut().physics_simulation.step(dt), move |alpha| render_scene.borrow_mut().render(alpha));
+    }
+
+    pub fn stop(&mut self) {
+        game_loop::stop(&self.game_loop);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.game_loop.borrow().is_running()
+    }
+
+    // paused, the loop keeps rendering but only steps when asked to with step_once
+    pub fn pause(&mut self) {
+        self.game_loop.borrow_mut().set_paused(true);
+    }
 
-        let mut physics_simulation = World::new(ConfigState::new());
-        for _ in 0..DEFAULT_MAX_PARTICLES {
-            physics_simulation.create_particle();
+    pub fn resume(&mut self) {
+        self.game_loop.borrow_mut().set_paused(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.game_loop.borrow().is_paused()
+    }
+
+    pub fn step_once(&mut self) {
+        self.game_loop.borrow_mut().request_step();
+    }
+
+    // simulated seconds per real second
+    pub fn set_speed(&mut self, speed: f32) {
+        self.game_loop.borrow_mut().speed = speed.max(0.);
+    }
+
+    pub fn set_fixed_timestep(&mut self, fixed_timestep: f32, max_catch_up_steps: u32) -> Result<(), Error> {
+        require_positive("fixed_timestep", fixed_timestep)?;
+        let mut game_loop = self.game_loop.borrow_mut();
+        game_loop.fixed_timestep = fixed_timestep;
+        game_loop.max_catch_up_steps = max_catch_up_steps.max(1);
+        Ok(())
+    }
+
+    // Size the canvas is shown at in CSS pixels, the drawing buffer gets one pixel per device pixel. For layouts that
+    // size the canvas themselves, set_auto_resize follows it instead.
+    pub fn resize(&mut self, css_width: f64, css_height: f64) {
+        self.scene.borrow_mut().render_context.resize(to_device_pixels(css_width), to_device_pixels(css_height));
+    }
+
+    // follow the size the page layout gives the canvas and devicePixelRatio changes, through a ResizeObserver
+    pub fn set_auto_resize(&mut self, enabled: bool) {
+        self.scene.borrow_mut().render_context.set_auto_resize(enabled);
+    }
+
+    // Camera controls. x and y are canvas positions in CSS pixels from the top left, like offsetX and offsetY of a
+    // mouse event. `x`, `y` of set_camera are the world point to center on.
+    pub fn set_camera(&mut self, x: f32, y: f32, zoom: f32, rotation: f32) -> Result<(), Error> {
+        require_positive("zoom", zoom)?;
+        let mut scene = self.scene.borrow_mut();
+        let camera = scene.render_context.get_mutable_camera();
+        camera.position = Vec2f::new(x, y);
+        camera.set_zoom(zoom);
+        camera.rotation = rotation;
+        Ok(())
+    }
+
+    // back to showing the whole world, which also stops following
+    pub fn reset_camera(&mut self) {
+        let mut scene = self.scene.borrow_mut();
+        scene.follow_particle = None;
+        scene.reset_camera();
+    }
+
+    pub fn set_zoom_limits(&mut self, min_zoom: f32, max_zoom: f32) -> Result<(), Error> {
+        require_positive("min_zoom", min_zoom)?;
+        require_positive("max_zoom", max_zoom)?;
+        if min_zoom > max_zoom {
+            return Err(Error::Config(format!("min_zoom {} is above max_zoom {}", min_zoom, max_zoom)));
         }
+        let mut scene = self.scene.borrow_mut();
+        let camera = scene.render_context.get_mutable_camera();
+        camera.min_zoom = min_zoom;
+        camera.max_zoom = max_zoom;
+        camera.set_zoom(camera.zoom);
+        Ok(())
+    }
 
-        return GameState {
-            render_context,
-            physics_simulation,
+    pub fn zoom(&self) -> f32 {
+        self.scene.borrow().render_context.get_camera().zoom
+    }
+
+    // deltaY of a wheel event in pixels, scrolling up zooms in around the cursor
+    pub fn on_wheel(&mut self, x: f32, y: f32, delta_y: f32) {
+        let factor = (-delta_y * self.wheel_zoom_speed).exp();
+        let mut scene = self.scene.borrow_mut();
+        let clip = scene.screen_to_clip(x, y);
+        scene.render_context.get_mutable_camera().zoom_at(clip, factor);
+    }
+
+    pub fn set_wheel_zoom_speed(&mut self, speed: f32) -> Result<(), Error> {
+        require_positive("wheel_zoom_speed", speed)?;
+        self.wheel_zoom_speed = speed;
+        Ok(())
+    }
+
+    // panning drags the world along with the cursor and stops following a particle
+    pub fn on_drag_start(&mut self, x: f32, y: f32) {
+        let mut scene = self.scene.borrow_mut();
+        scene.follow_particle = None;
+        let clip = scene.screen_to_clip(x, y);
+        scene.render_context.get_mutable_camera().begin_drag(clip);
+    }
+
+    pub fn on_drag_move(&mut self, x: f32, y: f32) {
+        let mut scene = self.scene.borrow_mut();
+        let clip = scene.screen_to_clip(x, y);
+        scene.render_context.get_mutable_camera().drag_to(clip);
+    }
+
+    pub fn on_drag_end(&mut self) {
+        self.scene.borrow_mut().render_context.get_mutable_camera().end_drag();
+    }
+
+    // the world point under a canvas position, as [x, y]
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Vec<f32> {
+        let world = self.scene.borrow().screen_to_world(x, y);
+        vec![world.x, world.y]
+    }
+
+    // index of the particle under a canvas position
+    pub fn pick_particle(&self, x: f32, y: f32) -> Option<usize> {
+        self.scene.borrow().pick_particle(x, y)
+    }
+
+    // keeps the camera centered on a particle, None stops following
+    pub fn follow_particle(&mut self, index: Option<usize>) {
+        self.scene.borrow_mut().follow_particle = index;
+    }
+
+    // while the browser has taken the WebGL context away nothing is drawn, the simulation keeps running
+    pub fn is_context_lost(&self) -> bool {
+        self.scene.borrow().render_context.is_context_lost()
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        self.world().stats()
+    }
+
+    pub fn timings(&self) -> PhaseTimings {
+        let scene = self.scene.borrow();
+        PhaseTimings::new(&scene.physics_simulation.profiler, scene.render_context.get_profiler())
+    }
+
+    pub fn add_spring(&mut self, a: usize, b: usize, stiffness: f32, damping: f32, break_strain: Option<f32>) -> bool {
+        self.world().add_spring(a, b, stiffness, damping, break_strain)
+    }
+
+    pub fn add_distance_constraint(&mut self, a: usize, b: usize, break_strain: Option<f32>) -> bool {
+        self.world().add_distance_constraint(a, b, break_strain)
+    }
+
+    pub fn spawn_soft_body(&mut self, x: f32, y: f32, radius: f32, n: usize) -> Option<usize> {
+        self.world().spawn_soft_body(Vec2f::new(x, y), radius, n)
+    }
+
+    pub fn set_solver_mode(&mut self, mode: SolverMode) {
+        self.world().config.solver_mode = mode;
+    }
+
+    pub fn set_sph_parameters(&mut self, smoothing_radius: f32, rest_density: f32, stiffness: f32, viscosity: f32, surface_tension: f32, equation_of_state: EquationOfState) -> Result<(), Error> {
+        require_positive("smoothing_radius", smoothing_radius)?;
+        require_positive("rest_density", rest_density)?;
+        self.world().config.sph = SphParameters { smoothing_radius, rest_density, stiffness, viscosity, surface_tension, equation_of_state };
+        Ok(())
+    }
+
+    pub fn set_xpbd_parameters(&mut self, substeps: usize, contact_compliance: f32, boundary_compliance: f32) -> Result<(), Error> {
+        if substeps == 0 {
+            return Err(Error::Config("substeps must be at least 1".into()));
         }
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.xpbd_substeps = substeps;
+        config.xpbd_contact_compliance = contact_compliance;
+        config.xpbd_boundary_compliance = boundary_compliance;
+        Ok(())
     }
 
-    pub fn update(&mut self, dt: f32) {
-        self.physics_simulation.step(dt);
+    pub fn set_species_count(&mut self, count: usize) {
+        let mut world = self.world();
+        world.set_species_count(count);
+        world.wake_all();
     }
 
-    pub fn render(&mut self) {
-        self.update_render_state();
-        self.render_context.dispatch_draw();
+    pub fn set_species_color(&mut self, species: usize, r: f32, g: f32, b: f32, a: f32) {
+        self.world().config.species.set_color(species, Vec4f::new(r, g, b, a));
     }
 
-    pub fn on_click(&mut self, _x: i32, _y: i32) {
-        let mut physics_simulation = World::new(ConfigState::new());
-        for _ in 0..DEFAULT_MAX_PARTICLES {
-            physics_simulation.create_particle();
+    pub fn set_species_radius(&mut self, species: usize, radius: f32) -> Result<(), Error> {
+        require_positive("radius", radius)?;
+        let mut world = self.world();
+        if species >= world.config.species.count() {
+            return Err(Error::Config(format!("no species {}, there are {}", species, world.config.species.count())));
         }
-        self.physics_simulation = physics_simulation;
+        world.config.species.set_radius(species, radius);
+        world.wake_all();
+        Ok(())
+    }
+
+    // strength > 0 pulls `a` towards `b`, strength < 0 pushes it away. The matrix is not symmetric.
+    pub fn set_interaction(&mut self, a: usize, b: usize, strength: f32, range: f32) {
+        let mut world = self.world();
+        world.config.species.set_interaction(a, b, strength, range);
+        world.wake_all();
+    }
+
+    pub fn set_particle_species(&mut self, index: usize, species: usize) {
+        let mut world = self.world();
+        world.set_particle_species(index, species);
+        world.wake_all();
+    }
+
+    pub fn randomize_species(&mut self) {
+        let mut world = self.world();
+        world.randomize_species();
+        world.wake_all();
+    }
+
+    pub fn set_mutual_gravity(&mut self, enabled: bool, gravitational_constant: f32, theta: f32, softening: f32) -> Result<(), Error> {
+        require_positive("theta", theta)?;
+        require_positive("softening", softening)?;
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.mutual_gravity = enabled;
+        config.gravitational_constant = gravitational_constant;
+        config.barnes_hut_theta = theta;
+        config.gravity_softening = softening;
+        world.wake_all();
+        Ok(())
+    }
+
+    pub fn set_gravity_vector(&mut self, x: f32, y: f32) {
+        let mut world = self.world();
+        world.config.gravity_vector = Vec2f::new(x, y);
+        world.wake_all();
     }
 
-    fn update_render_state(&mut self){
+    pub fn set_coulomb(&mut self, mode: CoulombMode, constant: f32, cutoff: f32, softening: f32) -> Result<(), Error> {
+        require_positive("cutoff", cutoff)?;
+        require_positive("softening", softening)?;
+        let mut world = self.world();
+        let coulomb = &mut world.config.coulomb;
+        coulomb.mode = mode;
+        coulomb.constant = constant;
+        coulomb.cutoff = cutoff;
+        coulomb.softening = softening;
+        world.wake_all();
+        Ok(())
+    }
+
+    pub fn set_electric_field(&mut self, x: f32, y: f32) {
+        let mut world = self.world();
+        world.config.electric_field = Vec2f::new(x, y);
+        world.wake_all();
+    }
+
+    pub fn set_particle_charge(&mut self, index: usize, charge: f32) {
+        let mut world = self.world();
+        world.set_particle_charge(index, charge);
+        world.wake_all();
+    }
+
+    pub fn alternate_charges(&mut self, magnitude: f32) {
+        let mut world = self.world();
+        world.alternate_charges(magnitude);
+        world.wake_all();
+    }
+
+    pub fn add_obstacle(&mut self, x: f32, y: f32, radius: f32, charge: f32) -> usize {
+        let mut world = self.world();
+        world.wake_all();
+        world.add_obstacle(Vec2f::new(x, y), radius, charge)
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        let mut world = self.world();
+        world.obstacles.clear();
+        world.wake_all();
+    }
+
+    pub fn set_particle_temperature(&mut self, index: usize, temperature: f32) {
+        self.world().set_particle_temperature(index, temperature);
+    }
+
+    // thermal_expansion > 0 makes particles hotter than ambient rise against gravity
+    pub fn set_heat_transfer(&mut self, ambient_temperature: f32, conductivity: f32, thermal_expansion: f32) {
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.ambient_temperature = ambient_temperature;
+        config.heat_conductivity = conductivity;
+        config.thermal_expansion = thermal_expansion;
+        world.wake_all();
+    }
+
+    // the target is a kinetic temperature, the mean kinetic energy per particle
+    pub fn set_thermostat(&mut self, mode: ThermostatMode, target: f32, tau: f32) -> Result<(), Error> {
+        require_positive("tau", tau)?;
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.thermostat = mode;
+        config.thermostat_target = target;
+        config.thermostat_tau = tau;
+        world.wake_all();
+        Ok(())
+    }
+
+    // `start` and `end` are measured along the wall, in world units. Returns the wall index.
+    pub fn add_thermal_wall(&mut self, side: WallSide, start: f32, end: f32, temperature: f32, conductivity: f32) -> usize {
+        self.world().add_thermal_wall(ThermalWall::new(side, start, end, temperature, conductivity))
+    }
+
+    pub fn clear_thermal_walls(&mut self) {
+        self.world().thermal_walls.clear();
+    }
+
+    pub fn set_color_by_temperature(&mut self, enabled: bool, cold: f32, hot: f32) {
+        let mut scene = self.scene.borrow_mut();
+        scene.color_by_temperature = enabled;
+        scene.temperature_range = (cold, hot);
+    }
+
+    // a solid rectangle of particles, `angle` in radians. Returns the rigid body index.
+    pub fn spawn_rigid_box(&mut self, x: f32, y: f32, width: f32, height: f32, angle: f32) -> Option<usize> {
+        self.world().spawn_rigid_box(Vec2f::new(x, y), width, height, angle)
+    }
+
+    // a solid disc of particles with `teeth` particles around the rim. Returns the rigid body index.
+    pub fn spawn_rigid_gear(&mut self, x: f32, y: f32, radius: f32, teeth: usize) -> Option<usize> {
+        self.world().spawn_rigid_gear(Vec2f::new(x, y), radius, teeth)
+    }
+
+    pub fn rigid_body_count(&self) -> usize {
+        self.world().rigid_bodies.len()
+    }
+
+    // granular mode only: particles slower than `speed` for `steps` steps in a row stop being simulated until hit
+    pub fn set_sleeping(&mut self, enabled: bool, speed: f32, steps: u32) -> Result<(), Error> {
+        require_positive("speed", speed)?;
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.sleeping = enabled;
+        config.sleep_speed = speed;
+        config.sleep_steps = steps;
+        Ok(())
+    }
+
+    // continuous collision detection for the granular and SPH integration, fast particles stop at what they would skip over
+    pub fn set_ccd_mode(&mut self, mode: CcdMode) {
+        self.world().config.ccd = mode;
+    }
+
+    pub fn set_particle_ccd(&mut self, index: usize, enabled: bool) {
+        self.world().set_particle_ccd(index, enabled);
+    }
+
+    // a particle that is always swept for continuous collision detection. Returns its index.
+    pub fn spawn_projectile(&mut self, x: f32, y: f32, vx: f32, vy: f32) -> usize {
+        let mut world = self.world();
+        let index = world.spawn_particle(Vec2f::new(x, y), Vec2f::new(vx, vy));
+        world.set_particle_ccd(index, true);
+        index
+    }
+
+    // `update(dt)` splits dt into as many steps as the CFL condition asks for, up to `max_steps`. dt is capped at
+    // `max_frame_time` first, the time past either limit is dropped.
+    pub fn set_adaptive_timestep(&mut self, enabled: bool, cfl_number: f32, max_steps: usize, max_frame_time: f32) -> Result<(), Error> {
+        require_positive("cfl_number", cfl_number)?;
+        require_positive("max_frame_time", max_frame_time)?;
+        let mut world = self.world();
+        let config = &mut world.config;
+        config.adaptive_timestep = enabled;
+        config.cfl_number = cfl_number;
+        config.max_adaptive_steps = max_steps.max(1);
+        config.max_frame_time = max_frame_time;
+        Ok(())
+    }
+
+    // how many internal steps the last update took
+    pub fn last_step_count(&self) -> usize {
+        self.world().last_step_count
+    }
+
+    pub fn constraint_count(&self) -> usize {
+        self.world().get_constraints().len()
+    }
+
+    // starts over with a freshly filled world, the camera stays where it is
+    pub fn reset_world(&mut self) {
+        self.scene.borrow_mut().reset_world();
+    }
+}
+
+impl GameState {
+    fn world(&self) -> RefMut<'_, World> {
+        RefMut::map(self.scene.borrow_mut(), |scene| &mut scene.physics_simulation)
+    }
+}
+
+// settings that only make sense above zero, anything else is rejected as a config error naming the setting
+fn require_positive(name: &str, value: f32) -> Result<(), Error> {
+    if value > 0. && value.is_finite() {
+        Ok(())
+    } else {
+        Err(Error::Config(format!("{} must be a positive number, got {}", name, value)))
+    }
+}
+
+// the world every scene starts with, the default config filled with particles
+fn default_world() -> World {
+    let mut physics_simulation = World::new(ConfigState::new());
+    for _ in 0..DEFAULT_MAX_PARTICLES {
+        physics_simulation.create_particle();
+    }
+    physics_simulation
+}
+
+impl<B: RenderBackend> Scene<B> {
+    pub(crate) fn new(mut render_context: Context<B>) -> Scene<B> {
+        let physics_simulation = default_world();
+        // letterboxed to the world bounds so circles stay round on any canvas shape
+        let bounds = physics_simulation.config.bounds;
+        render_context.set_aspect(Some(bounds.x as f32 / bounds.y as f32));
+        let mut scene = Scene {
+            render_context,
+            physics_simulation,
+            color_by_temperature: false,
+            temperature_range: DEFAULT_TEMPERATURE_RANGE,
+            follow_particle: None,
+        };
+        scene.reset_camera();
+        scene
+    }
+
+    pub(crate) fn reset_camera(&mut self) {
+        let bounds = self.physics_simulation.config.bounds;
+        *self.render_context.get_mutable_camera() = Camera2D::fit(Vec2f::new(bounds.x as f32, bounds.y as f32));
+    }
+
+    // canvas positions come in CSS pixels, the drawing buffer has devicePixelRatio times as many
+    fn screen_to_clip(&self, x: f32, y: f32) -> Vec2f {
+        let ratio = device_pixel_ratio() as f32;
+        self.render_context.screen_to_clip(x * ratio, y * ratio)
+    }
+
+    fn screen_to_world(&self, x: f32, y: f32) -> Vec2f {
+        self.render_context.get_camera().clip_to_world(self.screen_to_clip(x, y))
+    }
+
+    fn pick_particle(&self, x: f32, y: f32) -> Option<usize> {
+        self.physics_simulation.particle_at(self.screen_to_world(x, y))
+    }
+
+    // the indices of the old particles mean nothing in the new world, so following stops
+    fn reset_world(&mut self) {
+        self.physics_simulation = default_world();
+        self.follow_particle = None;
+    }
+
+    pub(crate) fn render(&mut self, alpha: f32) {
+        self.update_render_state(alpha.clamp(0., 1.));
+        self.render_context.dispatch_draw();
+    }
+
+    fn update_render_state(&mut self, alpha: f32){
         let particles = self.physics_simulation.get_particles();
+        let positions: Vec<Vec2f> = (0..particles.len()).map(|i| self.physics_simulation.interpolated_position(i, alpha)).collect();
+        // a particle that is gone, after a reset, ends following
+        self.follow_particle = self.follow_particle.filter(|&index| index < positions.len());
+        if let Some(index) = self.follow_particle {
+            self.render_context.get_mutable_camera().position = positions[index];
+        }
         let instances = self.render_context.get_mutable_instances();
+        // particles can be spawned after startup, grow the instance list to match
+        instances.resize(particles.len(), InstanceData::new(0.0, 0.0));
+        let species = &self.physics_simulation.config.species;
         for (i, particle) in particles.iter().enumerate() {
-            let position = world_to_gl(self.physics_simulation.config.bounds, particle.world_position);
-            instances[i].center_x = position.x;
-            instances[i].center_y = position.y;
+            instances[i].center_x = positions[i].x;
+            instances[i].center_y = positions[i].y;
+            // the circle mesh is built for DEFAULT_PARTICLE_RADIUS
+            instances[i].scale = species.radius(particle.species) / DEFAULT_PARTICLE_RADIUS;
+            let color = if self.color_by_temperature {
+                thermal::temperature_color(particle.temperature, self.temperature_range.0, self.temperature_range.1)
+            } else {
+                species.color(particle.species)
+            };
+            instances[i].color = color.as_float_array();
+        }
+
+        let lines = self.render_context.get_mutable_lines();
+        lines.clear();
+        for constraint in self.physics_simulation.get_constraints() {
+            lines.push_line(positions[constraint.a], positions[constraint.b]);
+        }
+        for obstacle in &self.physics_simulation.obstacles {
+            push_circle_outline(lines, obstacle.position, obstacle.radius);
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::SoftwareBackend;
+
+    // one drawing buffer pixel per world unit before the camera moves, the default bounds are square
+    fn headless_scene() -> Scene<SoftwareBackend> {
+        Scene::new(Context::headless(DEFAULT_BOUNDS.x as u32, DEFAULT_BOUNDS.y as u32).unwrap())
+    }
+
+    #[test]
+    fn picks_and_follows_a_particle() {
+        let mut scene = headless_scene();
+        let position = scene.physics_simulation.get_particles()[0].world_position;
+        // zoomed in on the particle, so it is under the middle of the canvas
+        let camera = scene.render_context.get_mutable_camera();
+        camera.position = position;
+        camera.set_zoom(4.);
+        let picked = scene.pick_particle(DEFAULT_BOUNDS.x as f32 / 2., DEFAULT_BOUNDS.y as f32 / 2.);
+        assert_eq!(picked, Some(0));
+
+        scene.follow_particle = picked;
+        scene.physics_simulation.step(0.01);
+        scene.render(1.);
+        let moved = scene.physics_simulation.get_particles()[0].world_position;
+        assert_eq!(scene.render_context.get_camera().position, moved);
+
+        scene.reset_world();
+        assert_eq!(scene.follow_particle, None);
+    }
+
+    #[test]
+    fn settings_must_be_positive_numbers() {
+        assert!(require_positive("tau", 0.5).is_ok());
+        for value in [0., -1., f32::NAN, f32::INFINITY] {
+            let Err(Error::Config(message)) = require_positive("tau", value) else { panic!("{} was accepted", value) };
+            assert!(message.starts_with("tau must be a positive number"));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use crate::game::game_state::Scene;
+use crate::game::world::World;
+use crate::renderer::{Camera2D, Context, SoftwareBackend};
+use crate::util::Error;
+
+// The simulation and the viewer without a browser. Steps the world natively and draws frames into an image with the
+// software backend, for thumbnails and animations rendered on machines without a GPU.
+pub struct HeadlessSimulation {
+    scene: Scene<SoftwareBackend>,
+}
+
+impl HeadlessSimulation {
+    // the same starting world as the browser viewer, drawn into a width x height image
+    pub fn new(width: u32, height: u32) -> Result<HeadlessSimulation, Error> {
+        crate::util::log::init();
+        Ok(HeadlessSimulation { scene: Scene::new(Context::headless(width, height)?) })
+    }
+
+    pub fn world(&mut self) -> &mut World {
+        &mut self.scene.physics_simulation
+    }
+
+    // the camera frames start out showing the whole world
+    pub fn camera(&mut self) -> &mut Camera2D {
+        self.scene.render_context.get_mutable_camera()
+    }
+
+    pub fn step(&mut self, dt: f32) {
+        self.scene.physics_simulation.step(dt);
+    }
+
+    // image size in pixels, the world stays letterboxed inside it
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.scene.render_context.resize(width, height);
+    }
+
+    pub fn set_antialiasing(&mut self, antialiasing: bool) {
+        self.scene.render_context.get_mutable_backend().set_antialiasing(antialiasing);
+    }
+
+    // draws the current state and returns the image it was drawn into
+    pub fn render(&mut self) -> &SoftwareBackend {
+        self.scene.render(1.);
+        self.scene.render_context.get_backend()
+    }
+
+    pub fn save_png(&mut self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let file = File::create(path)?;
+        self.render().write_png(BufWriter::new(file))
+    }
+
+    // Saves `frames` images `steps_per_frame` steps of `dt` apart as frame_0000.png, frame_0001.png, ... in `directory`.
+    // The first frame is the current state.
+    pub fn export_frames(&mut self, directory: impl AsRef<Path>, frames: usize, steps_per_frame: usize, dt: f32) -> Result<(), Error> {
+        std::fs::create_dir_all(&directory)?;
+        for frame in 0..frames {
+            if frame > 0 {
+                for _ in 0..steps_per_frame {
+                    self.step(dt);
+                }
+            }
+            self.save_png(directory.as_ref().join(format!("frame_{:04}.png", frame)))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_numbered_frames() {
+        let directory = std::env::temp_dir().join(format!("crab_gl_frames_{}", std::process::id()));
+        let mut simulation = HeadlessSimulation::new(40, 30).unwrap();
+        simulation.export_frames(&directory, 3, 2, 0.01).unwrap();
+
+        for frame in 0..3 {
+            let bytes = std::fs::read(directory.join(format!("frame_{:04}.png", frame))).unwrap();
+            let info = png::Decoder::new(bytes.as_slice()).read_info().unwrap().info().clone();
+            assert_eq!((info.width, info.height), (40, 30));
+        }
+        assert!(!directory.join("frame_0003.png").exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,4 +1,19 @@
 pub mod game_state;
+pub mod game_loop;
 pub use game_state::GameState;
 pub mod world;
-pub mod particle;
\ No newline at end of file
+pub mod particle;
+pub mod stats;
+pub mod constraint;
+pub mod soft_body;
+pub mod sph;
+pub mod xpbd;
+pub mod species;
+pub mod barnes_hut;
+pub mod obstacle;
+pub mod electrostatics;
+pub mod thermal;
+pub mod rigid_body;
+pub mod sleep;
+pub mod ccd;
+pub mod headless;
@@ -0,0 +1,43 @@
+use crate::game::particle::Particle;
+use crate::game::species::SpeciesTable;
+use crate::math::Vec2f;
+
+// Static circle that particles collide with. A non zero charge also makes it a fixed source for the Coulomb force.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Obstacle {
+    pub position: Vec2f,
+    pub radius: f32,
+    pub charge: f32,
+}
+
+impl Obstacle {
+    pub fn new(position: Vec2f, radius: f32, charge: f32) -> Self {
+        Obstacle { position, radius, charge }
+    }
+
+    // outward normal and penetration depth when the particle overlaps the obstacle
+    pub fn penetration(&self, position: Vec2f, particle_radius: f32) -> Option<(Vec2f, f32)> {
+        let delta = position.subtract(&self.position);
+        let distance = delta.length();
+        let depth = self.radius + particle_radius - distance;
+        if self.radius <= 0. || depth <= 0. {
+            return None;
+        }
+        let normal = if distance > f32::EPSILON { delta.scale(1. / distance) } else { Vec2f::new(0., 1.) };
+        Some((normal, depth))
+    }
+}
+
+// push particles out of obstacles and bounce them like a wall would
+pub fn collide_obstacles(particles: &mut [Particle], obstacles: &[Obstacle], species: &SpeciesTable, wall_damping: f32) {
+    for particle in particles.iter_mut() {
+        for obstacle in obstacles {
+            let Some((normal, depth)) = obstacle.penetration(particle.world_position, species.radius(particle.species)) else { continue };
+            particle.world_position = particle.world_position.add(&normal.scale(depth));
+            let normal_speed = particle.velocity.dot(&normal);
+            if normal_speed < 0. {
+                particle.velocity = particle.velocity.subtract(&normal.scale((1.0 + wall_damping) * normal_speed));
+            }
+        }
+    }
+}
@@ -4,11 +4,24 @@ use crate::math::Vec2f;
 pub struct Particle {
     pub world_position: Vec2f,
     pub velocity: Vec2f,
-    pub current_spatial_partition: (i32, i32)
+    pub current_spatial_partition: (i32, i32),
+    pub species: usize,
+    pub charge: f32,
+    pub temperature: f32,
+    pub rigid_body: Option<usize>, //index into World::rigid_bodies for members of a rigid cluster
+    pub asleep: bool,
+    pub rest_steps: u32, //steps in a row spent slower than the sleep threshold
+    pub ccd: bool, //always swept for continuous collision detection, whatever the configured mode
+    pub density: f32, //only maintained by the SPH solver
+    pub pressure: f32
 }
 
 impl Particle {
     pub fn new(world_position: Vec2f, velocity: Vec2f, current_spatial_partition: (i32, i32)) -> Self {
-        Particle { world_position, velocity, current_spatial_partition }
+        Particle { world_position, velocity, current_spatial_partition, species: 0, charge: 0., temperature: 0., rigid_body: None, asleep: false, rest_steps: 0, ccd: false, density: 0., pressure: 0. }
     }
-}
\ No newline at end of file
+
+    pub fn shares_rigid_body(&self, other: &Particle) -> bool {
+        self.rigid_body.is_some() && self.rigid_body == other.rigid_body
+    }
+}
@@ -0,0 +1,205 @@
+use crate::game::particle::Particle;
+use crate::math::Vec2f;
+
+// Cluster of particles that moves as one body. Contacts, walls and forces still act on the member particles one by
+// one, then shape matching gathers what happened to them into the motion of the whole body and puts every member
+// back at its place in the rigid shape. Members have unit mass like every other particle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigidBody {
+    pub members: Vec<usize>,
+    pub rest_offsets: Vec<Vec2f>, //member positions relative to the center of mass at orientation 0
+    pub mass: f32,
+    pub inertia: f32,
+    pub center: Vec2f,
+    pub orientation: f32, //radians, counter clockwise
+    pub velocity: Vec2f,
+    pub angular_velocity: f32,
+}
+
+fn rotate(vector: Vec2f, angle: f32) -> Vec2f {
+    let (sin, cos) = angle.sin_cos();
+    Vec2f::new(vector.x * cos - vector.y * sin, vector.x * sin + vector.y * cos)
+}
+
+fn cross(a: Vec2f, b: Vec2f) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+impl RigidBody {
+    // the members' current layout becomes the rest shape
+    pub fn new(members: Vec<usize>, particles: &[Particle]) -> Self {
+        let mass = members.len() as f32;
+        let center = members.iter()
+            .fold(Vec2f::new(0., 0.), |sum, member| sum.add(&particles[*member].world_position))
+            .scale(1. / mass);
+        let rest_offsets: Vec<Vec2f> = members.iter().map(|member| particles[*member].world_position.subtract(&center)).collect();
+        let inertia = rest_offsets.iter().map(|offset| offset.dot(offset)).sum::<f32>();
+        let mut body = RigidBody { members, rest_offsets, mass, inertia, center, orientation: 0., velocity: Vec2f::new(0., 0.), angular_velocity: 0. };
+        body.gather_velocity(particles);
+        body
+    }
+
+    // where a member belongs in the body's current pose
+    pub fn goal_position(&self, member: usize) -> Vec2f {
+        self.center.add(&rotate(self.rest_offsets[member], self.orientation))
+    }
+
+    // best fit translation and rotation of the rest shape onto the members' current positions
+    fn match_pose(&mut self, particles: &[Particle]) {
+        self.center = self.members.iter()
+            .fold(Vec2f::new(0., 0.), |sum, member| sum.add(&particles[*member].world_position))
+            .scale(1. / self.mass);
+        let (mut sin_sum, mut cos_sum) = (0., 0.);
+        for (member, rest_offset) in self.members.iter().zip(self.rest_offsets.iter()) {
+            let offset = particles[*member].world_position.subtract(&self.center);
+            sin_sum += cross(*rest_offset, offset);
+            cos_sum += rest_offset.dot(&offset);
+        }
+        // a single particle body, or one that collapsed onto its center, keeps its orientation
+        if sin_sum != 0. || cos_sum != 0. {
+            self.orientation = f32::atan2(sin_sum, cos_sum);
+        }
+    }
+
+    // total linear and angular momentum of the members, divided by mass and inertia
+    fn gather_velocity(&mut self, particles: &[Particle]) {
+        self.velocity = self.members.iter()
+            .fold(Vec2f::new(0., 0.), |sum, member| sum.add(&particles[*member].velocity))
+            .scale(1. / self.mass);
+        if self.inertia <= f32::EPSILON {
+            self.angular_velocity = 0.;
+            return;
+        }
+        let angular_momentum: f32 = self.members.iter().map(|member| {
+            let particle = &particles[*member];
+            cross(particle.world_position.subtract(&self.center), particle.velocity.subtract(&self.velocity))
+        }).sum();
+        self.angular_velocity = angular_momentum / self.inertia;
+    }
+
+    // snap every member onto the rigid shape
+    pub fn match_positions(&mut self, particles: &mut [Particle]) {
+        self.match_pose(particles);
+        for index in 0..self.members.len() {
+            particles[self.members[index]].world_position = self.goal_position(index);
+        }
+    }
+
+    // snap every member onto the rigid shape and give it the velocity of that point of the body
+    pub fn match_positions_and_velocities(&mut self, particles: &mut [Particle]) {
+        self.match_pose(particles);
+        self.gather_velocity(particles);
+        for index in 0..self.members.len() {
+            let goal = self.goal_position(index);
+            let arm = goal.subtract(&self.center);
+            let particle = &mut particles[self.members[index]];
+            particle.world_position = goal;
+            particle.velocity = self.velocity.add(&Vec2f::new(-arm.y, arm.x).scale(self.angular_velocity));
+        }
+    }
+
+    // after a position based step the velocities are already derived from the motion, only read them back
+    pub fn update_velocity(&mut self, particles: &[Particle]) {
+        self.gather_velocity(particles);
+    }
+}
+
+// Particles on a grid filling a width x height rectangle, `spacing` apart, rotated by `angle` around `center`.
+pub fn box_layout(center: Vec2f, width: f32, height: f32, angle: f32, spacing: f32) -> Vec<Vec2f> {
+    let columns = ((width / spacing).round() as usize).max(1);
+    let rows = ((height / spacing).round() as usize).max(1);
+    let origin = Vec2f::new((columns - 1) as f32 * spacing, (rows - 1) as f32 * spacing).scale(-0.5);
+    let mut points = Vec::with_capacity(columns * rows);
+    for row in 0..rows {
+        for column in 0..columns {
+            let local = origin.add(&Vec2f::new(column as f32 * spacing, row as f32 * spacing));
+            points.push(center.add(&rotate(local, angle)));
+        }
+    }
+    points
+}
+
+// Rings of particles making a solid disc of `radius`, with `teeth` particles sticking out of the rim.
+pub fn gear_layout(center: Vec2f, radius: f32, teeth: usize, spacing: f32) -> Vec<Vec2f> {
+    let mut points = vec![center];
+    let mut ring_radius = spacing;
+    while ring_radius <= radius + spacing / 2.0 {
+        let count = ((2.0 * std::f32::consts::PI * ring_radius / spacing).floor() as usize).max(1);
+        for i in 0..count {
+            let angle = 2.0 * std::f32::consts::PI * i as f32 / count as f32;
+            points.push(center.add(&Vec2f::new(ring_radius * angle.cos(), ring_radius * angle.sin())));
+        }
+        ring_radius += spacing;
+    }
+    let tooth_radius = ring_radius;
+    for i in 0..teeth {
+        let angle = 2.0 * std::f32::consts::PI * i as f32 / teeth as f32;
+        points.push(center.add(&Vec2f::new(tooth_radius * angle.cos(), tooth_radius * angle.sin())));
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body_and_particles() -> (RigidBody, Vec<Particle>) {
+        let particles: Vec<Particle> = box_layout(Vec2f::new(100., 100.), 30., 20., 0., 10.).into_iter()
+            .map(|point| Particle::new(point, Vec2f::new(0., 0.), (0, 0)))
+            .collect();
+        (RigidBody::new((0..particles.len()).collect(), &particles), particles)
+    }
+
+    fn assert_near(actual: Vec2f, expected: Vec2f) {
+        assert!(actual.subtract(&expected).length() < 1e-3, "{:?} != {:?}", actual, expected);
+    }
+
+    #[test]
+    fn recovers_a_rigid_motion() {
+        let (mut body, mut particles) = body_and_particles();
+        let shift = Vec2f::new(5., -3.);
+        for (particle, offset) in particles.iter_mut().zip(body.rest_offsets.iter()) {
+            particle.world_position = Vec2f::new(100., 100.).add(&shift).add(&rotate(*offset, 0.6));
+        }
+        let moved: Vec<Vec2f> = particles.iter().map(|particle| particle.world_position).collect();
+        body.match_positions(&mut particles);
+
+        assert!((body.orientation - 0.6).abs() < 1e-4);
+        assert_near(body.center, Vec2f::new(105., 97.));
+        for (particle, position) in particles.iter().zip(moved) {
+            assert_near(particle.world_position, position);
+        }
+    }
+
+    #[test]
+    fn pushed_members_are_put_back_into_shape() {
+        let (mut body, mut particles) = body_and_particles();
+        particles[0].world_position = particles[0].world_position.add(&Vec2f::new(-4., 2.));
+        body.match_positions(&mut particles);
+        for a in 0..particles.len() {
+            for b in 0..particles.len() {
+                let distance = particles[a].world_position.subtract(&particles[b].world_position).length();
+                let rest_distance = body.rest_offsets[a].subtract(&body.rest_offsets[b]).length();
+                assert!((distance - rest_distance).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn a_kick_on_one_member_moves_and_spins_the_body() {
+        let (mut body, mut particles) = body_and_particles();
+        particles[0].velocity = Vec2f::new(0., 6.);
+        body.match_positions_and_velocities(&mut particles);
+
+        // momentum is shared out, not lost
+        let momentum = particles.iter().fold(Vec2f::new(0., 0.), |sum, particle| sum.add(&particle.velocity));
+        assert_near(momentum, Vec2f::new(0., 6.));
+        assert_near(body.velocity, Vec2f::new(0., 1.));
+        // hit upwards on its lower left corner, so the body turns clockwise
+        assert!(body.angular_velocity < 0.);
+        for (index, particle) in particles.iter().enumerate() {
+            let arm = body.goal_position(index).subtract(&body.center);
+            assert_near(particle.velocity, body.velocity.add(&Vec2f::new(-arm.y, arm.x).scale(body.angular_velocity)));
+        }
+    }
+}
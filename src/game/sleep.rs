@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use crate::game::constraint::Constraint;
+use crate::game::obstacle::Obstacle;
+use crate::game::particle::Particle;
+use crate::game::rigid_body::RigidBody;
+use crate::game::species::SpeciesTable;
+use crate::math::{Vec2i, Vec2f};
+
+// Contacts this much beyond the contact distance still join an island, resting particles hover right at it.
+const ISLAND_SKIN: f32 = 0.1;
+
+// Counts how many steps in a row each awake particle has moved slower than `speed_threshold` and puts it to sleep
+// after `steps`, as long as something holds it up: a wall gravity pushes it into, an obstacle or a particle already asleep. Piles fall
+// asleep from the floor up and a slow cloud drifting through the air never freezes in place. Particles joined by links
+// or a rigid body only sleep as one group, once every member has rested and one of them is held up, so nothing hangs
+// from a sleeping anchor.
+#[allow(clippy::too_many_arguments)]
+pub fn update_sleep(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable, bounds: Vec2i, gravity: Vec2f,
+                    obstacles: &[Obstacle], constraints: &[Constraint], rigid_bodies: &[RigidBody], speed_threshold: f32, steps: u32) {
+    for particle in particles.iter_mut().filter(|particle| !particle.asleep) {
+        if particle.velocity.length() < speed_threshold {
+            particle.rest_steps = particle.rest_steps.saturating_add(1);
+        } else {
+            particle.rest_steps = 0;
+        }
+    }
+
+    // support is judged on the sleepers from before this step, like a pile falling asleep one layer at a time
+    let falling_asleep: Vec<Vec<usize>> = linked_groups(particles.len(), constraints, rigid_bodies).into_iter()
+        .filter(|group| group.iter().all(|member| !particles[*member].asleep && particles[*member].rest_steps >= steps))
+        .filter(|group| group.iter().any(|member| is_supported(particles, *member, spatial_partition, reach, species, bounds, gravity, obstacles)))
+        .collect();
+    for member in falling_asleep.into_iter().flatten() {
+        fall_asleep(&mut particles[member]);
+    }
+}
+
+// A link between a sleeping and an awake particle pulls on something that cannot move. Returns the sleeping ends, so
+// their groups can be woken and move as one again.
+pub fn stressed_links(particles: &[Particle], constraints: &[Constraint]) -> Vec<usize> {
+    constraints.iter()
+        .filter(|constraint| particles[constraint.a].asleep != particles[constraint.b].asleep)
+        .map(|constraint| if particles[constraint.a].asleep { constraint.a } else { constraint.b })
+        .collect()
+}
+
+// particles joined through links or a shared rigid body, every particle is in exactly one group
+fn linked_groups(count: usize, constraints: &[Constraint], rigid_bodies: &[RigidBody]) -> Vec<Vec<usize>> {
+    let mut parents: Vec<usize> = (0..count).collect();
+    fn root(parents: &mut [usize], mut index: usize) -> usize {
+        while parents[index] != index {
+            parents[index] = parents[parents[index]];
+            index = parents[index];
+        }
+        index
+    }
+    let pairs = constraints.iter().map(|constraint| (constraint.a, constraint.b))
+        .chain(rigid_bodies.iter().flat_map(|body| body.members.windows(2).map(|pair| (pair[0], pair[1]))));
+    for (a, b) in pairs {
+        let (root_a, root_b) = (root(&mut parents, a), root(&mut parents, b));
+        parents[root_a] = root_b;
+    }
+
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for index in 0..count {
+        groups.entry(root(&mut parents, index)).or_default().push(index);
+    }
+    groups.into_values().collect()
+}
+
+fn fall_asleep(particle: &mut Particle) {
+    particle.asleep = true;
+    particle.velocity.x = 0.;
+    particle.velocity.y = 0.;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn is_supported(particles: &[Particle], index: usize, spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable,
+                bounds: Vec2i, gravity: Vec2f, obstacles: &[Obstacle]) -> bool {
+    let particle = &particles[index];
+    let radius = species.radius(particle.species);
+    let skin = radius * (1.0 + ISLAND_SKIN);
+    let position = particle.world_position;
+    // without gravity every wall holds a particle, otherwise only the ones it is pressed against
+    let weightless = gravity.x == 0. && gravity.y == 0.;
+    let on_wall = (position.x <= skin && (weightless || gravity.x < 0.))
+        || (position.x >= bounds.x as f32 - skin && (weightless || gravity.x > 0.))
+        || (position.y <= skin && (weightless || gravity.y < 0.))
+        || (position.y >= bounds.y as f32 - skin && (weightless || gravity.y > 0.));
+    if on_wall {
+        return true;
+    }
+    if obstacles.iter().any(|obstacle| obstacle.position.subtract(&position).length() < (obstacle.radius + radius) * (1.0 + ISLAND_SKIN)) {
+        return true;
+    }
+    let (cell_x, cell_y) = particle.current_spatial_partition;
+    for dx in -reach.0..=reach.0 {
+        for dy in -reach.1..=reach.1 {
+            let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+            for neighbor in cell.iter().map(|neighbor| *neighbor as usize).filter(|neighbor| particles[*neighbor].asleep) {
+                let contact_distance = radius + species.radius(particles[neighbor].species);
+                if particles[neighbor].world_position.subtract(&position).length() < contact_distance * (1.0 + ISLAND_SKIN) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+// Wakes every sleeping particle connected to the seeds through touching contacts, links or a shared rigid body.
+pub fn wake_islands(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable,
+                    constraints: &[Constraint], rigid_bodies: &[RigidBody], seeds: Vec<usize>) {
+    let mut links: HashMap<usize, Vec<usize>> = HashMap::new();
+    for constraint in constraints {
+        links.entry(constraint.a).or_default().push(constraint.b);
+        links.entry(constraint.b).or_default().push(constraint.a);
+    }
+
+    let mut stack = seeds;
+    while let Some(index) = stack.pop() {
+        if !particles[index].asleep {
+            continue;
+        }
+        wake(&mut particles[index]);
+
+        if let Some(body) = particles[index].rigid_body.and_then(|body| rigid_bodies.get(body)) {
+            stack.extend(body.members.iter().filter(|member| particles[**member].asleep));
+        }
+        if let Some(linked) = links.get(&index) {
+            stack.extend(linked.iter().filter(|other| particles[**other].asleep));
+        }
+        let (cell_x, cell_y) = particles[index].current_spatial_partition;
+        for dx in -reach.0..=reach.0 {
+            for dy in -reach.1..=reach.1 {
+                let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                for neighbor in cell.iter().map(|neighbor| *neighbor as usize).filter(|neighbor| particles[*neighbor].asleep) {
+                    let distance = particles[index].world_position.subtract(&particles[neighbor].world_position).length();
+                    let contact_distance = species.radius(particles[index].species) + species.radius(particles[neighbor].species);
+                    if distance < contact_distance * (1.0 + ISLAND_SKIN) {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn wake(particle: &mut Particle) {
+    particle.asleep = false;
+    particle.rest_steps = 0;
+}
+
+pub fn wake_all(particles: &mut [Particle]) {
+    particles.iter_mut().for_each(wake);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::constraint::ConstraintKind;
+    use crate::math::Vec4f;
+
+    const STEPS: u32 = 5;
+    const SPEED: f32 = 1.;
+
+    #[test]
+    fn hanging_chain_sleeps_and_wakes_as_one() {
+        let species = SpeciesTable::new(Vec4f::new(1., 1., 1., 1.), 5.);
+        // the top link rests against an obstacle, the rest of the chain hangs below it without touching
+        let obstacles = [Obstacle::new(Vec2f::new(50., 100.), 10., 0.)];
+        let mut particles: Vec<Particle> = (0..4).map(|i| Particle::new(Vec2f::new(50., 85. - 20. * i as f32), Vec2f::new(0., 0.), (0, 0))).collect();
+        let constraints: Vec<Constraint> = (0..3).map(|i| Constraint::new(i, i + 1, 20., ConstraintKind::Distance, None)).collect();
+        let spatial_partition = HashMap::from([((0, 0), vec![0, 1, 2, 3])]);
+        let update = |particles: &mut Vec<Particle>| update_sleep(particles, &spatial_partition, (1, 1), &species, Vec2i{ x: 200, y: 200 }, Vec2f::new(0., -10.),
+                                                                  &obstacles, &constraints, &[], SPEED, STEPS);
+
+        // the supported top has rested long enough, but the bottom is still swinging
+        for _ in 0..2 * STEPS {
+            particles[3].velocity = Vec2f::new(2. * SPEED, 0.);
+            update(&mut particles);
+        }
+        assert!(particles.iter().all(|particle| !particle.asleep));
+
+        // all at once, as soon as the last one has rested long enough
+        particles[3].velocity = Vec2f::new(0., 0.);
+        for _ in 0..STEPS - 1 {
+            update(&mut particles);
+        }
+        assert!(particles.iter().all(|particle| !particle.asleep));
+        update(&mut particles);
+        assert!(particles.iter().all(|particle| particle.asleep));
+
+        // the bottom woken on its own pulls on its sleeping neighbour, which wakes the whole chain
+        wake(&mut particles[3]);
+        let stressed = stressed_links(&particles, &constraints);
+        assert_eq!(stressed, vec![2]);
+        wake_islands(&mut particles, &spatial_partition, (1, 1), &species, &constraints, &[], stressed);
+        assert!(particles.iter().all(|particle| !particle.asleep));
+    }
+}
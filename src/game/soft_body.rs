@@ -0,0 +1,101 @@
+use crate::game::particle::Particle;
+use crate::math::Vec2f;
+
+// Closed ring of particles, counter clockwise. The edges are ordinary springs in World::constraints,
+// the ring itself only adds the pressure that keeps the enclosed area close to its rest value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoftBody {
+    pub ring: Vec<usize>,
+    pub rest_area: f32,
+    pub pressure: f32,
+}
+
+impl SoftBody {
+    pub fn new(ring: Vec<usize>, rest_area: f32, pressure: f32) -> Self {
+        SoftBody { ring, rest_area, pressure }
+    }
+
+    // signed area of the ring, positive while it stays counter clockwise
+    pub fn area(&self, particles: &[Particle]) -> f32 {
+        let mut twice_area = 0.;
+        for (i, index) in self.ring.iter().enumerate() {
+            let a = particles[*index].world_position;
+            let b = particles[self.ring[(i + 1) % self.ring.len()]].world_position;
+            twice_area += a.x * b.y - b.x * a.y;
+        }
+        twice_area / 2.0
+    }
+
+    // push every edge along its outward normal, proportional to the edge length and the relative area loss
+    pub fn apply_pressure(&self, particles: &mut [Particle], dt: f32) {
+        if self.rest_area <= 0. {
+            return;
+        }
+        let pressure = self.pressure * (self.rest_area - self.area(particles)) / self.rest_area;
+        for i in 0..self.ring.len() {
+            let a = self.ring[i];
+            let b = self.ring[(i + 1) % self.ring.len()];
+            let edge = particles[b].world_position.subtract(&particles[a].world_position);
+            // for a counter clockwise ring the outward normal of an edge is the edge rotated clockwise,
+            // whose length is the edge length, so the force is already scaled by it
+            let outward = Vec2f::new(edge.y, -edge.x);
+            let impulse = outward.scale(pressure * dt / 2.0);
+            particles[a].velocity = particles[a].velocity.add(&impulse);
+            particles[b].velocity = particles[b].velocity.add(&impulse);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_state::ConfigState;
+    use crate::game::world::World;
+
+    fn square(side: f32) -> Vec<Particle> {
+        [(0., 0.), (side, 0.), (side, side), (0., side)].iter().map(|(x, y)| Particle::new(Vec2f::new(*x, *y), Vec2f::new(0., 0.), (0, 0))).collect()
+    }
+
+    #[test]
+    fn area_is_signed_by_winding() {
+        let particles = square(2.);
+        assert_eq!(SoftBody::new(vec![0, 1, 2, 3], 4., 1.).area(&particles), 4.);
+        assert_eq!(SoftBody::new(vec![3, 2, 1, 0], 4., 1.).area(&particles), -4.);
+    }
+
+    #[test]
+    fn pressure_pushes_a_squashed_ring_outwards() {
+        let body = SoftBody::new(vec![0, 1, 2, 3], 8., 10.);
+        let mut particles = square(2.);
+        body.apply_pressure(&mut particles, 0.1);
+        let center = Vec2f::new(1., 1.);
+        for particle in &particles {
+            let outwards = particle.world_position.subtract(&center);
+            assert!(particle.velocity.dot(&outwards) > 0.);
+        }
+        let momentum = particles.iter().fold(Vec2f::new(0., 0.), |sum, particle| sum.add(&particle.velocity));
+        assert!(momentum.length() < 1e-5);
+
+        let mut resting = square(2.);
+        SoftBody::new(vec![0, 1, 2, 3], 4., 10.).apply_pressure(&mut resting, 0.1);
+        assert!(resting.iter().all(|particle| particle.velocity == Vec2f::new(0., 0.)));
+    }
+
+    fn area_after_landing(pressure: f32) -> f32 {
+        let mut world = World::new(ConfigState::new());
+        world.config.soft_body_pressure = pressure;
+        let body = world.spawn_soft_body(Vec2f::new(400., 60.), 50., 16).unwrap();
+        for _ in 0..600 {
+            world.step(1. / 60.);
+        }
+        world.soft_bodies[body].area(&world.particles) / world.soft_bodies[body].rest_area
+    }
+
+    #[test]
+    fn pressure_keeps_a_landed_ring_inflated() {
+        // the edge springs alone fold up under gravity
+        assert!(area_after_landing(0.) < 0.5);
+        assert!(area_after_landing(200.) > 0.95);
+        assert!((area_after_landing(20.) - 1.).abs() < 0.15);
+    }
+}
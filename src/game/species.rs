@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use crate::game::particle::Particle;
+use crate::math::{Vec2f, Vec4f};
+
+// Per species look and size, plus the "particle life" interaction matrix between every pair of species.
+pub struct SpeciesTable {
+    count: usize,
+    colors: Vec<Vec4f>,
+    radii: Vec<f32>,
+    strengths: Vec<f32>, //row major, [a * count + b] is how hard a is pulled towards b, negative pushes it away
+    ranges: Vec<f32>,
+}
+
+impl SpeciesTable {
+    // a single species that does not interact with itself, which is the plain granular simulation
+    pub fn new(color: Vec4f, radius: f32) -> Self {
+        SpeciesTable { count: 1, colors: vec![color], radii: vec![radius], strengths: vec![0.], ranges: vec![0.] }
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // grow or shrink the table, new species copy the look of species 0 and have no interactions
+    pub fn resize(&mut self, count: usize) {
+        let count = count.max(1);
+        let mut strengths = vec![0.; count * count];
+        let mut ranges = vec![0.; count * count];
+        for a in 0..count.min(self.count) {
+            for b in 0..count.min(self.count) {
+                strengths[a * count + b] = self.strengths[a * self.count + b];
+                ranges[a * count + b] = self.ranges[a * self.count + b];
+            }
+        }
+        self.colors.resize(count, self.colors[0]);
+        self.radii.resize(count, self.radii[0]);
+        self.strengths = strengths;
+        self.ranges = ranges;
+        self.count = count;
+    }
+
+    // out of range species fall back to the last one, so a shrunk table never panics
+    fn clamp(&self, species: usize) -> usize {
+        species.min(self.count - 1)
+    }
+
+    pub fn color(&self, species: usize) -> Vec4f {
+        self.colors[self.clamp(species)]
+    }
+
+    pub fn set_color(&mut self, species: usize, color: Vec4f) {
+        if species < self.count {
+            self.colors[species] = color;
+        }
+    }
+
+    pub fn radius(&self, species: usize) -> f32 {
+        self.radii[self.clamp(species)]
+    }
+
+    pub fn set_radius(&mut self, species: usize, radius: f32) {
+        if species < self.count && radius > 0. {
+            self.radii[species] = radius;
+        }
+    }
+
+    pub fn max_radius(&self) -> f32 {
+        self.radii.iter().copied().fold(0., f32::max)
+    }
+
+    pub fn strength(&self, a: usize, b: usize) -> f32 {
+        self.strengths[self.clamp(a) * self.count + self.clamp(b)]
+    }
+
+    pub fn range(&self, a: usize, b: usize) -> f32 {
+        self.ranges[self.clamp(a) * self.count + self.clamp(b)]
+    }
+
+    pub fn max_range(&self) -> f32 {
+        self.ranges.iter().copied().fold(0., f32::max)
+    }
+
+    pub fn set_interaction(&mut self, a: usize, b: usize, strength: f32, range: f32) {
+        if a < self.count && b < self.count {
+            self.strengths[a * self.count + b] = strength;
+            self.ranges[a * self.count + b] = range.max(0.);
+        }
+    }
+}
+
+// Particle life force: beyond contact distance the pull rises linearly to `strength` halfway through the range
+// and falls back to zero at the range. Contact itself is left to the collision solver.
+fn interaction_force(distance: f32, contact_distance: f32, range: f32, strength: f32) -> f32 {
+    if distance <= contact_distance || distance >= range {
+        return 0.;
+    }
+    let t = (distance - contact_distance) / (range - contact_distance);
+    strength * (1.0 - (2.0 * t - 1.0).abs())
+}
+
+pub fn apply_interactions(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable, dt: f32) {
+    let accelerations: Vec<Vec2f> = (0..particles.len()).map(|index| {
+        let particle = &particles[index];
+        let (cell_x, cell_y) = particle.current_spatial_partition;
+        let mut acceleration = Vec2f::new(0., 0.);
+        for dx in -reach.0..=reach.0 {
+            for dy in -reach.1..=reach.1 {
+                let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                for neighbor in cell {
+                    let other = &particles[*neighbor as usize];
+                    let range = species.range(particle.species, other.species);
+                    let strength = species.strength(particle.species, other.species);
+                    if *neighbor as usize == index || range <= 0. || strength == 0. {
+                        continue;
+                    }
+                    let delta = other.world_position.subtract(&particle.world_position);
+                    let distance = delta.length();
+                    let contact_distance = species.radius(particle.species) + species.radius(other.species);
+                    let force = interaction_force(distance, contact_distance, range, strength);
+                    if force != 0. {
+                        acceleration = acceleration.add(&delta.scale(force / distance));
+                    }
+                }
+            }
+        }
+        acceleration
+    }).collect();
+
+    // sleeping particles still pull and push the others but do not move themselves
+    for (particle, acceleration) in particles.iter_mut().zip(accelerations).filter(|(particle, _)| !particle.asleep) {
+        particle.velocity = particle.velocity.add(&acceleration.scale(dt));
+    }
+}
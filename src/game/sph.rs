@@ -0,0 +1,185 @@
+use wasm_bindgen::prelude::*;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use crate::game::particle::Particle;
+use crate::game::world::World;
+use crate::math::{Vec2i, Vec2f};
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EquationOfState {
+    // p = k((rho / rho0)^7 - 1), stiff and close to incompressible
+    Tait,
+    // p = k(rho - rho0), softer and cheaper
+    IdealGas,
+}
+
+const TAIT_GAMMA: i32 = 7;
+
+pub struct SphParameters {
+    pub smoothing_radius: f32,
+    pub rest_density: f32,
+    pub stiffness: f32,
+    pub viscosity: f32,
+    pub surface_tension: f32,
+    pub equation_of_state: EquationOfState,
+}
+
+// 2D kernels from Müller et al. 2003, particles are unit mass
+fn poly6(r_squared: f32, h: f32) -> f32 {
+    let h_squared = h * h;
+    if r_squared >= h_squared {
+        return 0.;
+    }
+    4.0 / (PI * h.powi(8)) * (h_squared - r_squared).powi(3)
+}
+
+fn spiky_gradient_magnitude(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.;
+    }
+    -30.0 / (PI * h.powi(5)) * (h - r).powi(2)
+}
+
+fn viscosity_laplacian(r: f32, h: f32) -> f32 {
+    if r >= h {
+        return 0.;
+    }
+    40.0 / (PI * h.powi(5)) * (h - r)
+}
+
+fn pressure(density: f32, parameters: &SphParameters) -> f32 {
+    match parameters.equation_of_state {
+        EquationOfState::Tait => parameters.stiffness * ((density / parameters.rest_density).powi(TAIT_GAMMA) - 1.0),
+        EquationOfState::IdealGas => parameters.stiffness * (density - parameters.rest_density),
+    }
+}
+
+// every particle within the smoothing radius of `index`, found through the spatial partitions
+fn for_each_neighbor(particles: &[Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), index: usize, h: f32, mut f: impl FnMut(usize, Vec2f, f32)) {
+    let (cell_x, cell_y) = particles[index].current_spatial_partition;
+    let position = particles[index].world_position;
+    for dx in -reach.0..=reach.0 {
+        for dy in -reach.1..=reach.1 {
+            if let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) {
+                for neighbor in cell {
+                    let neighbor = *neighbor as usize;
+                    let delta = position.subtract(&particles[neighbor].world_position);
+                    let distance = delta.length();
+                    if distance < h {
+                        f(neighbor, delta, distance);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn solve(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, bounds: Vec2i, partition_radius: f32, parameters: &SphParameters, dt: f32) {
+    let h = parameters.smoothing_radius;
+    let reach = World::get_partition_reach(bounds, partition_radius, h);
+
+    // density and pressure, including each particle's own contribution
+    for index in 0..particles.len() {
+        let mut density = 0.;
+        for_each_neighbor(particles, spatial_partition, reach, index, h, |_, _, distance| {
+            density += poly6(distance * distance, h);
+        });
+        particles[index].density = density;
+        particles[index].pressure = pressure(density, parameters);
+    }
+
+    let accelerations: Vec<Vec2f> = (0..particles.len()).map(|index| {
+        let particle = &particles[index];
+        let mut force = Vec2f::new(0., 0.);
+        for_each_neighbor(particles, spatial_partition, reach, index, h, |neighbor, delta, distance| {
+            if neighbor == index || distance <= f32::EPSILON {
+                return;
+            }
+            let other = &particles[neighbor];
+            let direction = delta.scale(1. / distance);
+
+            let pressure_term = (particle.pressure + other.pressure) / (2.0 * other.density);
+            force = force.subtract(&direction.scale(pressure_term * spiky_gradient_magnitude(distance, h)));
+
+            let viscosity_term = parameters.viscosity * viscosity_laplacian(distance, h) / other.density;
+            force = force.add(&other.velocity.subtract(&particle.velocity).scale(viscosity_term));
+
+            // cohesion pulls neighbors together, which is what keeps a surface from evaporating
+            force = force.subtract(&delta.scale(parameters.surface_tension * poly6(distance * distance, h)));
+        });
+        if particle.density > 0. { force.scale(1. / particle.density) } else { force }
+    }).collect();
+
+    for (particle, acceleration) in particles.iter_mut().zip(accelerations) {
+        particle.velocity = particle.velocity.add(&acceleration.scale(dt));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOUNDS: Vec2i = Vec2i{ x: 800, y: 800 };
+    const PARTITIONS: f32 = 100.;
+
+    fn parameters(rest_density: f32) -> SphParameters {
+        SphParameters { smoothing_radius: 20., rest_density, stiffness: 30., viscosity: 0., surface_tension: 0., equation_of_state: EquationOfState::IdealGas }
+    }
+
+    fn particles(positions: impl Iterator<Item = Vec2f>) -> Vec<Particle> {
+        positions.map(|position| Particle::new(position, Vec2f::new(0., 0.), World::get_spatial_index(position, BOUNDS, PARTITIONS))).collect()
+    }
+
+    fn partitions(particles: &[Particle]) -> HashMap<(i32, i32), Vec<i32>> {
+        let mut spatial_partition: HashMap<(i32, i32), Vec<i32>> = HashMap::new();
+        for (index, particle) in particles.iter().enumerate() {
+            spatial_partition.entry(particle.current_spatial_partition).or_default().push(index as i32);
+        }
+        spatial_partition
+    }
+
+    #[test]
+    fn poly6_holds_unit_mass() {
+        let h = 20.;
+        let ring_width = 0.01;
+        let mass: f32 = (0..2000).map(|i| {
+            let r = (i as f32 + 0.5) * ring_width;
+            poly6(r * r, h) * 2. * PI * r * ring_width
+        }).sum();
+        assert!((mass - 1.).abs() < 1e-3, "{}", mass);
+    }
+
+    #[test]
+    fn lone_particle_only_counts_itself() {
+        let mut particles = particles([Vec2f::new(400., 400.)].into_iter());
+        let spatial_partition = partitions(&particles);
+        solve(&mut particles, &spatial_partition, BOUNDS, PARTITIONS, &parameters(0.01), 0.01);
+        let own_density = 4. / (PI * 400.);
+        assert!((particles[0].density - own_density).abs() < 1e-7);
+        assert!((particles[0].pressure - 30. * (own_density - 0.01)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn lattice_density_is_one_over_the_cell_area() {
+        let spacing = 5.;
+        let mut particles = particles((-10..=10).flat_map(|i| (-10..=10).map(move |j| Vec2f::new(400. + spacing * i as f32, 400. + spacing * j as f32))));
+        let spatial_partition = partitions(&particles);
+        solve(&mut particles, &spatial_partition, BOUNDS, PARTITIONS, &parameters(0.01), 0.01);
+        let center = &particles[particles.len() / 2];
+        assert_eq!(center.world_position, Vec2f::new(400., 400.));
+        assert!((center.density * spacing * spacing - 1.).abs() < 0.03, "{}", center.density);
+        // the edges miss the neighbors outside the lattice
+        assert!(particles[0].density < 0.5 * center.density);
+    }
+
+    #[test]
+    fn compressed_pairs_push_apart() {
+        let mut particles = particles([Vec2f::new(400., 400.), Vec2f::new(405., 400.)].into_iter());
+        let spatial_partition = partitions(&particles);
+        solve(&mut particles, &spatial_partition, BOUNDS, PARTITIONS, &parameters(0.), 0.01);
+        assert!(particles[0].pressure > 0.);
+        assert!(particles[0].velocity.x < 0. && particles[1].velocity.x > 0.);
+        assert!((particles[0].velocity.x + particles[1].velocity.x).abs() < 1e-6);
+    }
+}
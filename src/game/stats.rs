@@ -0,0 +1,115 @@
+use wasm_bindgen::prelude::*;
+use crate::game::world::World;
+use crate::math::Vec2f;
+
+// Snapshot of the simulation health. Particles are treated as unit mass.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default)]
+pub struct WorldStats {
+    pub particle_count: usize,
+    pub sleeping_count: usize,
+    pub steps_taken: usize, //internal steps of the last update, more than one with the adaptive timestep
+    pub kinetic_energy: f32,
+    pub potential_energy: f32,
+    pub momentum_x: f32,
+    pub momentum_y: f32,
+    pub max_speed: f32,
+    pub max_overlap_depth: f32,
+    pub average_neighbors: f32, //how many particles each particle touches, on average
+    occupancy_histogram: Vec<u32>, //index is the number of particles in a partition, value is how many partitions hold that many
+}
+
+#[wasm_bindgen]
+impl WorldStats {
+    pub fn occupancy_histogram(&self) -> Vec<u32> {
+        self.occupancy_histogram.clone()
+    }
+}
+
+impl WorldStats {
+    pub fn collect(world: &World) -> WorldStats {
+        let particles = world.get_particles();
+        let gravity = world.config.gravity_vector;
+        let species = &world.config.species;
+
+        let mut stats = WorldStats { particle_count: particles.len(), ..Default::default() };
+        stats.steps_taken = world.last_step_count;
+        stats.sleeping_count = particles.iter().filter(|particle| particle.asleep).count();
+        let mut momentum = Vec2f::new(0., 0.);
+        let mut total_neighbors: usize = 0;
+        let reach = World::get_partition_reach(world.config.bounds, world.config.spatial_partition_size, 2.0 * species.max_radius());
+
+        for (index, particle) in particles.iter().enumerate() {
+            let speed = particle.velocity.length();
+            stats.kinetic_energy += 0.5 * speed * speed;
+            // potential energy is measured against the origin, along the gravity vector
+            stats.potential_energy -= gravity.x * particle.world_position.x + gravity.y * particle.world_position.y;
+            momentum = momentum.add(&particle.velocity);
+            stats.max_speed = stats.max_speed.max(speed);
+
+            // neighbors are the particles touching this one, searched in the same partitions solve_overlap checks
+            let (cell_x, cell_y) = particle.current_spatial_partition;
+            for dx in -reach.0..=reach.0 {
+                for dy in -reach.1..=reach.1 {
+                    if let Some(cell) = world.spatial_partition.get(&(cell_x + dx, cell_y + dy)) {
+                        for neighbor in cell.iter().filter(|neighbor| **neighbor != index as i32) {
+                            let other = &particles[*neighbor as usize];
+                            let distance = particle.world_position.subtract(&other.world_position).length();
+                            let contact_distance = species.radius(particle.species) + species.radius(other.species);
+                            if distance <= contact_distance {
+                                total_neighbors += 1;
+                            }
+                            if particle.shares_rigid_body(other) {
+                                continue;
+                            }
+                            stats.max_overlap_depth = stats.max_overlap_depth.max(contact_distance - distance);
+                        }
+                    }
+                }
+            }
+        }
+
+        stats.momentum_x = momentum.x;
+        stats.momentum_y = momentum.y;
+        if !particles.is_empty() {
+            stats.average_neighbors = total_neighbors as f32 / particles.len() as f32;
+        }
+
+        for cell in world.spatial_partition.values() {
+            let occupancy = cell.len();
+            if stats.occupancy_histogram.len() <= occupancy {
+                stats.occupancy_histogram.resize(occupancy + 1, 0);
+            }
+            stats.occupancy_histogram[occupancy] += 1;
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::game_state::ConfigState;
+
+    #[test]
+    fn sums_energy_momentum_and_contacts() {
+        let mut world = World::new(ConfigState::new());
+        // two overlapping by 2, one on its own
+        world.spawn_particle(Vec2f::new(100., 100.), Vec2f::new(3., 4.));
+        world.spawn_particle(Vec2f::new(108., 100.), Vec2f::new(0., 0.));
+        world.spawn_particle(Vec2f::new(300., 300.), Vec2f::new(-1., 0.));
+        let stats = world.stats();
+
+        assert_eq!(stats.particle_count, 3);
+        assert_eq!(stats.sleeping_count, 0);
+        assert!((stats.kinetic_energy - 13.).abs() < 1e-4);
+        // 9.8 times the summed heights
+        assert!((stats.potential_energy - 4900.).abs() < 1e-2);
+        assert_eq!((stats.momentum_x, stats.momentum_y), (2., 4.));
+        assert_eq!(stats.max_speed, 5.);
+        assert!((stats.max_overlap_depth - 2.).abs() < 1e-4);
+        assert!((stats.average_neighbors - 2. / 3.).abs() < 1e-4);
+        // the touching pair straddles a partition border, so each particle has a partition to itself
+        assert_eq!(stats.occupancy_histogram()[1..], [3]);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use crate::game::particle::Particle;
+use crate::game::species::SpeciesTable;
+use crate::math::{Vec2i, Vec2f, Vec4f};
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WallSide {
+    Left,
+    Right,
+    Bottom,
+    Top,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThermostatMode {
+    Off,
+    // relaxes towards the target with time constant tau
+    Berendsen,
+    // rescales straight to the target every step
+    VelocityRescale,
+}
+
+// Stretch of a boundary wall held at a fixed temperature, from `start` to `end` along the wall
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalWall {
+    pub side: WallSide,
+    pub start: f32,
+    pub end: f32,
+    pub temperature: f32,
+    pub conductivity: f32, //per second, how fast a touching particle approaches the wall temperature
+}
+
+impl ThermalWall {
+    pub fn new(side: WallSide, start: f32, end: f32, temperature: f32, conductivity: f32) -> Self {
+        ThermalWall { side, start: start.min(end), end: start.max(end), temperature, conductivity }
+    }
+
+    // a particle touches the wall when it is within its radius of it and alongside the segment
+    fn touches(&self, position: Vec2f, radius: f32, bounds: Vec2i) -> bool {
+        let (along, distance) = match self.side {
+            WallSide::Left => (position.y, position.x),
+            WallSide::Right => (position.y, bounds.x as f32 - position.x),
+            WallSide::Bottom => (position.x, position.y),
+            WallSide::Top => (position.x, bounds.y as f32 - position.y),
+        };
+        distance <= radius * 1.01 && along >= self.start && along <= self.end
+    }
+}
+
+// Conduction between two touching particles, proportional to how deep they overlap. The difference decays
+// exponentially over dt, so the result does not depend on the step size and never overshoots the mean.
+pub fn exchange_heat(particles: &mut [Particle], a: usize, b: usize, overlap: f32, conductivity: f32, dt: f32) {
+    if conductivity <= 0. {
+        return;
+    }
+    let rate = 0.5 * (1. - (-2. * conductivity * overlap * dt).exp());
+    let flow = (particles[b].temperature - particles[a].temperature) * rate;
+    particles[a].temperature += flow;
+    particles[b].temperature -= flow;
+}
+
+// Position solvers leave touching particles resting exactly at contact distance, so contacts within this fraction
+// of the contact distance still conduct.
+const CONTACT_SKIN: f32 = 0.05;
+
+// contact conduction as a pass of its own, for the solvers that resolve overlap without World::solve_overlap
+pub fn conduct(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable, conductivity: f32, dt: f32) {
+    if conductivity <= 0. {
+        return;
+    }
+    for a in 0..particles.len() {
+        let (cell_x, cell_y) = particles[a].current_spatial_partition;
+        for dx in -reach.0..=reach.0 {
+            for dy in -reach.1..=reach.1 {
+                let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                // every pair is visited from both sides, only the lower index exchanges
+                for b in cell.iter().map(|neighbor| *neighbor as usize).filter(|b| *b > a) {
+                    let distance = particles[a].world_position.subtract(&particles[b].world_position).length();
+                    let contact_distance = (species.radius(particles[a].species) + species.radius(particles[b].species)) * (1.0 + CONTACT_SKIN);
+                    if distance < contact_distance {
+                        exchange_heat(particles, a, b, contact_distance - distance, conductivity, dt);
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn apply_thermal_walls(particles: &mut [Particle], walls: &[ThermalWall], bounds: Vec2i, species: &SpeciesTable, dt: f32) {
+    for particle in particles.iter_mut() {
+        for wall in walls {
+            if wall.touches(particle.world_position, species.radius(particle.species), bounds) {
+                let rate = 1. - (-wall.conductivity.max(0.) * dt).exp();
+                particle.temperature += (wall.temperature - particle.temperature) * rate;
+            }
+        }
+    }
+}
+
+// hot particles are lighter: push against gravity in proportion to how far above ambient they are
+pub fn apply_buoyancy(particles: &mut [Particle], gravity_vector: Vec2f, ambient_temperature: f32, thermal_expansion: f32, dt: f32) {
+    if thermal_expansion == 0. {
+        return;
+    }
+    for particle in particles.iter_mut().filter(|particle| !particle.asleep) {
+        let lift = -thermal_expansion * (particle.temperature - ambient_temperature);
+        particle.velocity = particle.velocity.add(&gravity_vector.scale(lift * dt));
+    }
+}
+
+// mean kinetic energy per particle, which is the kinetic temperature for unit mass in 2D with k_B = 1.
+// Particles with a non finite velocity are left out so one of them cannot poison the thermostat, and sleeping ones
+// because they are frozen rather than cold.
+pub fn kinetic_temperature(particles: &[Particle]) -> f32 {
+    let energies: Vec<f32> = particles.iter()
+        .filter(|particle| !particle.asleep)
+        .map(|particle| 0.5 * particle.velocity.dot(&particle.velocity))
+        .filter(|energy| energy.is_finite())
+        .collect();
+    if energies.is_empty() {
+        return 0.;
+    }
+    energies.iter().sum::<f32>() / energies.len() as f32
+}
+
+pub fn apply_thermostat(particles: &mut [Particle], mode: ThermostatMode, target: f32, tau: f32, dt: f32) {
+    let current = kinetic_temperature(particles);
+    if current <= f32::EPSILON {
+        return;
+    }
+    let scale_squared = match mode {
+        ThermostatMode::Off => return,
+        ThermostatMode::Berendsen => 1.0 + (dt / tau.max(dt)) * (target / current - 1.0),
+        ThermostatMode::VelocityRescale => target / current,
+    };
+    let scale = scale_squared.max(0.).sqrt();
+    for particle in particles.iter_mut().filter(|particle| !particle.asleep) {
+        particle.velocity = particle.velocity.scale(scale);
+    }
+}
+
+// blue through white to red between the cold and hot ends of the display range
+pub fn temperature_color(temperature: f32, cold: f32, hot: f32) -> Vec4f {
+    let t = if hot > cold { ((temperature - cold) / (hot - cold)).clamp(0., 1.) } else { 0.5 };
+    if t < 0.5 {
+        let s = t * 2.0;
+        Vec4f::new(s, s, 1.0, 1.0)
+    } else {
+        let s = (1.0 - t) * 2.0;
+        Vec4f::new(1.0, s, s, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(temperature_a: f32, temperature_b: f32) -> Vec<Particle> {
+        let mut a = Particle::new(Vec2f::new(0., 0.), Vec2f::new(0., 0.), (0, 0));
+        let mut b = Particle::new(Vec2f::new(1., 0.), Vec2f::new(0., 0.), (0, 0));
+        a.temperature = temperature_a;
+        b.temperature = temperature_b;
+        vec![a, b]
+    }
+
+    #[test]
+    fn exchange_does_not_depend_on_the_step_size() {
+        let mut one_step = pair(0., 100.);
+        exchange_heat(&mut one_step, 0, 1, 2., 1.5, 0.1);
+        let mut ten_steps = pair(0., 100.);
+        for _ in 0..10 {
+            exchange_heat(&mut ten_steps, 0, 1, 2., 1.5, 0.01);
+        }
+        assert!((one_step[0].temperature - ten_steps[0].temperature).abs() < 1e-3);
+        assert!((one_step[0].temperature + one_step[1].temperature - 100.).abs() < 1e-3);
+
+        // a huge step settles at the mean instead of overshooting it
+        let mut huge_step = pair(0., 100.);
+        exchange_heat(&mut huge_step, 0, 1, 2., 1.5, 1000.);
+        assert!((huge_step[0].temperature - 50.).abs() < 1e-3);
+        assert!((huge_step[1].temperature - 50.).abs() < 1e-3);
+    }
+}
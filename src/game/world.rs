@@ -1,34 +1,33 @@
 use crate::game::particle::Particle;
-use crate::math::{Vec2i, Vec2f};
-use crate::math::random::random_float;
-use crate::game::game_state::ConfigState;
+use crate::game::constraint::{Constraint, ConstraintKind};
+use crate::game::soft_body::SoftBody;
+use crate::game::rigid_body::{self, RigidBody};
+use crate::game::species::{self, SpeciesTable};
+use crate::math::{Vec2i, Vec2f, random_float};
+use crate::game::game_state::{ConfigState, SolverMode};
+use crate::game::{ccd, sleep, sph, xpbd};
+use crate::game::barnes_hut::QuadTree;
+use crate::game::electrostatics;
+use crate::game::thermal::{self, ThermalWall};
+use crate::game::obstacle::{self, Obstacle};
+use crate::game::stats::WorldStats;
+use crate::util::profiler::{now_ms, ProfilePhase, Profiler};
 use std::collections::HashMap;
-use crate::util::log::logger::*;
+use crate::util::log::{enabled, log_throttled, LogLevel};
 
 #[derive()]
 pub struct World {
     pub particles: Vec<Particle>,
     pub spatial_partition: HashMap<(i32, i32), Vec<i32>>, //key is (row, column), value is a list of particle indexes that live in that partition
-    pub config: ConfigState
-}
-
-pub fn world_to_gl(bounds: Vec2i, world: Vec2f) -> Vec2f {
-    //scale to the range of 0 to 1, then -1 to 1
-    let x = world.x / bounds.x as f32;
-    let y = world.y / bounds.y as f32;
-    let gl_x = x * 2.0 - 1.0;
-    let gl_y = y * 2.0 - 1.0;
-    Vec2f::new(gl_x, gl_y)
-}
-
-#[allow(unused)]
-pub fn gl_to_world(bounds: Vec2i, gl: Vec2f) -> Vec2f {
-    //scale to the range of 0 to 1, then -1 to 1
-    let x = (gl.x + 1.0) / 2.0;
-    let y = (gl.y + 1.0) / 2.0;
-    let world_x = x * bounds.x as f32;
-    let world_y = y * bounds.y as f32;
-    Vec2f::new(world_x, world_y)
+    pub constraints: Vec<Constraint>,
+    pub soft_bodies: Vec<SoftBody>,
+    pub rigid_bodies: Vec<RigidBody>,
+    pub obstacles: Vec<Obstacle>,
+    pub thermal_walls: Vec<ThermalWall>,
+    pub config: ConfigState,
+    pub profiler: Profiler,
+    pub last_step_count: usize, //internal steps the last call to step took
+    previous_positions: Vec<Vec2f>, //positions before the last call to step, for render interpolation
 }
 
 impl World {
@@ -36,16 +35,167 @@ impl World {
     pub fn new(config: ConfigState) -> Self {
         let particles = Vec::new();
         let spatial_partition = World::new_spatial_partition_map(config.bounds, config.spatial_partition_size, config.max_particles);
-        World { particles, spatial_partition, config }
+        let constraints = Vec::new();
+        let soft_bodies = Vec::new();
+        let rigid_bodies = Vec::new();
+        let obstacles = Vec::new();
+        let thermal_walls = Vec::new();
+        let profiler = Profiler::new();
+        World { particles, spatial_partition, constraints, soft_bodies, rigid_bodies, obstacles, thermal_walls, config, profiler, last_step_count: 0, previous_positions: Vec::new() }
     }
 
     pub fn create_particle(&mut self) {
         let random_position = Vec2f{x: random_float(0., self.config.bounds.x as f32),y: random_float(0., self.config.bounds.y as f32)};
-        let spatial_partition = World::get_spatial_index(random_position, self.config.bounds, self.config.spatial_partition_size);
-        let position = random_position;
         let velocity = Vec2f::new(random_float(-10., 100.), 0.);
-        let particle = Particle::new(position, velocity, spatial_partition);
+        self.spawn_particle(random_position, velocity);
+    }
+
+    // adds a particle and returns its index
+    pub fn spawn_particle(&mut self, position: Vec2f, velocity: Vec2f) -> usize {
+        let spatial_partition = World::get_spatial_index(position, self.config.bounds, self.config.spatial_partition_size);
+        let mut particle = Particle::new(position, velocity, spatial_partition);
+        particle.temperature = self.config.ambient_temperature;
         self.particles.push(particle);
+        // filed right away, so the particle can be found before the next step rebuilds the grid
+        let index = self.particles.len() - 1;
+        self.spatial_partition.entry(spatial_partition).or_default().push(index as i32);
+        index
+    }
+
+    // ring of n particles joined by edge springs, with pressure holding its area. Returns the soft body index.
+    pub fn spawn_soft_body(&mut self, center: Vec2f, radius: f32, n: usize) -> Option<usize> {
+        if n < 3 || radius <= 0. {
+            return None;
+        }
+        let ring: Vec<usize> = (0..n).map(|i| {
+            let angle = 2.0 * std::f32::consts::PI * (i as f32) / (n as f32);
+            let position = center.add(&Vec2f::new(radius * angle.cos(), radius * angle.sin()));
+            self.spawn_particle(position, Vec2f::new(0., 0.))
+        }).collect();
+
+        for i in 0..n {
+            self.add_spring(ring[i], ring[(i + 1) % n], self.config.soft_body_stiffness, self.config.soft_body_damping, None);
+        }
+
+        let mut soft_body = SoftBody::new(ring, 0., self.config.soft_body_pressure);
+        soft_body.rest_area = soft_body.area(&self.particles);
+        self.soft_bodies.push(soft_body);
+        Some(self.soft_bodies.len() - 1)
+    }
+
+    // one rigid body made of a particle at each point, in the shape they are spawned in. Returns the rigid body index.
+    pub fn spawn_rigid_body(&mut self, points: &[Vec2f], velocity: Vec2f) -> Option<usize> {
+        if points.is_empty() {
+            return None;
+        }
+        let body_index = self.rigid_bodies.len();
+        let members: Vec<usize> = points.iter().map(|point| {
+            let index = self.spawn_particle(*point, velocity);
+            self.particles[index].rigid_body = Some(body_index);
+            index
+        }).collect();
+        self.rigid_bodies.push(RigidBody::new(members, &self.particles));
+        Some(body_index)
+    }
+
+    // members are packed one particle diameter apart so they only just touch
+    pub fn spawn_rigid_box(&mut self, center: Vec2f, width: f32, height: f32, angle: f32) -> Option<usize> {
+        let spacing = 2.0 * self.config.particle_radius;
+        self.spawn_rigid_body(&rigid_body::box_layout(center, width, height, angle, spacing), Vec2f::new(0., 0.))
+    }
+
+    pub fn spawn_rigid_gear(&mut self, center: Vec2f, radius: f32, teeth: usize) -> Option<usize> {
+        let spacing = 2.0 * self.config.particle_radius;
+        self.spawn_rigid_body(&rigid_body::gear_layout(center, radius, teeth, spacing), Vec2f::new(0., 0.))
+    }
+
+    // links two particles with a damped spring, rest length is their current distance
+    pub fn add_spring(&mut self, a: usize, b: usize, stiffness: f32, damping: f32, break_strain: Option<f32>) -> bool {
+        self.add_constraint(a, b, ConstraintKind::Spring { stiffness, damping }, break_strain)
+    }
+
+    // links two particles rigidly at their current distance
+    pub fn add_distance_constraint(&mut self, a: usize, b: usize, break_strain: Option<f32>) -> bool {
+        self.add_constraint(a, b, ConstraintKind::Distance, break_strain)
+    }
+
+    fn add_constraint(&mut self, a: usize, b: usize, kind: ConstraintKind, break_strain: Option<f32>) -> bool {
+        if a == b || a >= self.particles.len() || b >= self.particles.len() {
+            return false;
+        }
+        let rest_length = World::distance(self.particles[a].world_position, self.particles[b].world_position);
+        self.constraints.push(Constraint::new(a, b, rest_length, kind, break_strain));
+        true
+    }
+
+    // resize the species table, particles of a removed species move to the last remaining one
+    pub fn set_species_count(&mut self, count: usize) {
+        self.config.species.resize(count);
+        let last = self.config.species.count() - 1;
+        for particle in self.particles.iter_mut() {
+            particle.species = particle.species.min(last);
+        }
+    }
+
+    pub fn set_particle_species(&mut self, index: usize, species: usize) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.species = species.min(self.config.species.count() - 1);
+        }
+    }
+
+    pub fn randomize_species(&mut self) {
+        let count = self.config.species.count();
+        for particle in self.particles.iter_mut() {
+            particle.species = (random_float(0., count as f32) as usize).min(count - 1);
+        }
+    }
+
+    // Sleeping particles are only at rest under the forces they fell asleep with, after gravity, a force or an
+    // obstacle changes they have to find out again.
+    pub fn wake_all(&mut self) {
+        sleep::wake_all(&mut self.particles);
+    }
+
+    // static circle, also a fixed point charge when `charge` is non zero. Returns the obstacle index.
+    pub fn add_obstacle(&mut self, position: Vec2f, radius: f32, charge: f32) -> usize {
+        self.obstacles.push(Obstacle::new(position, radius, charge));
+        self.obstacles.len() - 1
+    }
+
+    pub fn set_particle_charge(&mut self, index: usize, charge: f32) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.charge = charge;
+        }
+    }
+
+    // alternate +magnitude and -magnitude so the whole world stays neutral
+    pub fn alternate_charges(&mut self, magnitude: f32) {
+        for (index, particle) in self.particles.iter_mut().enumerate() {
+            particle.charge = if index % 2 == 0 { magnitude } else { -magnitude };
+        }
+    }
+
+    // `enabled` sweeps this particle for continuous collision detection even while the configured mode is off
+    pub fn set_particle_ccd(&mut self, index: usize, enabled: bool) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.ccd = enabled;
+        }
+    }
+
+    pub fn set_particle_temperature(&mut self, index: usize, temperature: f32) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.temperature = temperature;
+        }
+    }
+
+    // heated or cooled stretch of a boundary wall. Returns the wall index.
+    pub fn add_thermal_wall(&mut self, wall: ThermalWall) -> usize {
+        self.thermal_walls.push(wall);
+        self.thermal_walls.len() - 1
+    }
+
+    pub fn get_constraints(&self) -> &Vec<Constraint> {
+        &self.constraints
     }
 
     pub fn get_spatial_index(position: Vec2f, bounds: Vec2i, partition_radius:f32) -> (i32, i32) {
@@ -56,6 +206,13 @@ impl World {
         (x, y)
     }
 
+    // how many partitions to search on each side of a particle to find everything within `distance`
+    pub fn get_partition_reach(bounds: Vec2i, partition_radius: f32, distance: f32) -> (i32, i32) {
+        let x_partition_size = bounds.x as f32 / partition_radius;
+        let y_partition_size = bounds.y as f32 / partition_radius;
+        ((distance / x_partition_size).ceil() as i32, (distance / y_partition_size).ceil() as i32)
+    }
+
     fn new_spatial_partition_map(bounds: Vec2i, partition_radius: f32, max_particles: usize) -> HashMap<(i32, i32), Vec<i32>> {
         let num_x_partitions =(bounds.x as f32 / partition_radius).round() as i32;
         let num_y_partitions = (bounds.y as f32 / partition_radius).round() as i32;
@@ -104,31 +261,276 @@ impl World {
         neighbors
     }
 
+    // where the particle was `alpha` of the way through the last step, particles spawned since then sit still
+    pub fn interpolated_position(&self, index: usize, alpha: f32) -> Vec2f {
+        let current = self.particles[index].world_position;
+        match self.previous_positions.get(index) {
+            Some(previous) => previous.add(&current.subtract(previous).scale(alpha)),
+            None => current,
+        }
+    }
+
     pub fn get_particles(&self) -> &Vec<Particle> {
         &self.particles
     }
 
+    // the particle whose circle contains `point`, the one with the closest center where circles overlap
+    pub fn particle_at(&self, point: Vec2f) -> Option<usize> {
+        let (cell_x, cell_y) = World::get_spatial_index(point, self.config.bounds, self.config.spatial_partition_size);
+        let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, self.config.species.max_radius());
+        (-reach.0..=reach.0)
+            .flat_map(|dx| (-reach.1..=reach.1).map(move |dy| (cell_x + dx, cell_y + dy)))
+            .filter_map(|cell| self.spatial_partition.get(&cell))
+            .flatten()
+            .map(|neighbor| *neighbor as usize)
+            .map(|i| (i, self.particles[i].world_position.subtract(&point).length(), self.config.species.radius(self.particles[i].species)))
+            .filter(|(_, distance, radius)| distance <= radius)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _, _)| i)
+    }
+
+    pub fn stats(&self) -> WorldStats {
+        WorldStats::collect(self)
+    }
+
     pub fn step(&mut self, dt: f32) {
-        let adjusted_dt = dt * self.config.time_multiplier;
+        self.previous_positions.clear();
+        self.previous_positions.extend(self.particles.iter().map(|particle| particle.world_position));
+        if !self.config.adaptive_timestep {
+            self.last_step_count = 1;
+            self.advance(dt * self.config.time_multiplier);
+            return;
+        }
+        // a long frame (the tab was in the background) is cut short instead of replayed as a flood of steps
+        let mut remaining = dt.min(self.config.max_frame_time) * self.config.time_multiplier;
+        let mut steps = 0;
+        while remaining > 0. && steps < self.config.max_adaptive_steps {
+            // split what is left evenly, so the last step is not a sliver
+            let step_count = (remaining / self.cfl_timestep()).ceil().max(1.);
+            let step_dt = remaining / step_count;
+            self.advance(step_dt);
+            remaining -= step_dt;
+            steps += 1;
+        }
+        self.last_step_count = steps;
+        if remaining > 0. && enabled(module_path!(), LogLevel::Debug) {
+            log_throttled(module_path!(), &format!("adaptive timestep hit {} steps, dropped {:.4}s of simulated time", steps, remaining), LogLevel::Debug, 1000.);
+        } else if enabled(module_path!(), LogLevel::Trace) {
+            log_throttled(module_path!(), &format!("adaptive timestep took {} steps", steps), LogLevel::Trace, 1000.);
+        }
+    }
+
+    // CFL condition: no particle moves further than a fraction of the smallest radius in one step
+    fn cfl_timestep(&self) -> f32 {
+        let max_speed = self.particles.iter()
+            .filter(|particle| !particle.asleep)
+            .map(|particle| particle.velocity.length())
+            .filter(|speed| speed.is_finite())
+            .fold(0., f32::max);
+        let min_radius = (0..self.config.species.count()).map(|species| self.config.species.radius(species)).fold(f32::MAX, f32::min);
+        if max_speed <= f32::EPSILON {
+            return f32::MAX;
+        }
+        self.config.cfl_number * min_radius / max_speed
+    }
 
-        self.particles.iter_mut().for_each(|particle| {
-            World::apply_bounds(particle, self.config.bounds, self.config.wall_damping, self.config.particle_radius);
+    // one step of `adjusted_dt`, already scaled by the time multiplier
+    fn advance(&mut self, adjusted_dt: f32) {
+        if self.config.solver_mode == SolverMode::Xpbd {
+            sleep::wake_all(&mut self.particles);
+            self.step_xpbd(adjusted_dt);
+            return;
+        }
+        // only the granular solver knows how to treat sleeping particles as static
+        let sleeping = self.config.sleeping && self.config.solver_mode == SolverMode::Granular;
+        if !sleeping {
+            sleep::wake_all(&mut self.particles);
+        } else {
+            let stressed = sleep::stressed_links(&self.particles, &self.constraints);
+            if !stressed.is_empty() {
+                let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, 2.0 * self.config.species.max_radius());
+                sleep::wake_islands(&mut self.particles, &self.spatial_partition, reach, &self.config.species, &self.constraints, &self.rigid_bodies, stressed);
+            }
+        }
+
+        let start = now_ms();
+        self.particles.iter_mut().filter(|particle| !particle.asleep).for_each(|particle| {
+            World::apply_bounds(particle, self.config.bounds, self.config.wall_damping, self.config.species.radius(particle.species));
+        });
+        obstacle::collide_obstacles(&mut self.particles, &self.obstacles, &self.config.species, self.config.wall_damping);
+        self.profiler.record(ProfilePhase::Bounds, start);
+
+        let start = now_ms();
+        self.particles.iter_mut().filter(|particle| !particle.asleep).for_each(|particle| {
             World::apply_gravity(particle, adjusted_dt, self.config.gravity_vector);
         });
+        self.profiler.record(ProfilePhase::Gravity, start);
+
+        let start = now_ms();
+        self.apply_forces(adjusted_dt);
+        self.apply_thermal(adjusted_dt);
+        self.profiler.record(ProfilePhase::Forces, start);
+
+        let start = now_ms();
+        World::apply_springs(&mut self.particles, &self.constraints, adjusted_dt);
+        World::apply_pressure(&mut self.particles, &self.soft_bodies, adjusted_dt);
+        let mut constraints_ms = now_ms() - start;
 
-        World::solve_overlap(&mut self.particles, &self.spatial_partition,  self.config.particle_radius);
+        let start = now_ms();
+        match self.config.solver_mode {
+            SolverMode::Granular => {
+                let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, 2.0 * self.config.species.max_radius());
+                let woken = World::solve_overlap(&mut self.particles, &self.spatial_partition, reach, &self.config.species, self.config.heat_conductivity, self.config.sleep_speed, adjusted_dt);
+                if !woken.is_empty() {
+                    sleep::wake_islands(&mut self.particles, &self.spatial_partition, reach, &self.config.species, &self.constraints, &self.rigid_bodies, woken);
+                }
+            }
+            SolverMode::Sph => {
+                sph::solve(&mut self.particles, &self.spatial_partition, self.config.bounds, self.config.spatial_partition_size, &self.config.sph, adjusted_dt);
+                self.conduct_heat(adjusted_dt);
+            }
+            SolverMode::Xpbd => unreachable!("xpbd runs its own step"),
+        }
         //World::solve_overlap_n2(&mut self.particles, self.config.particle_radius);
+        self.profiler.record(ProfilePhase::OverlapSolve, start);
+
+        let start = now_ms();
+        World::solve_constraints(&mut self.particles, &mut self.constraints, self.config.constraint_iterations);
+        for body in self.rigid_bodies.iter_mut() {
+            // members sleep together, a sleeping body stays where it fell asleep
+            if body.members.iter().any(|member| self.particles[*member].asleep) {
+                continue;
+            }
+            body.match_positions_and_velocities(&mut self.particles);
+        }
+        constraints_ms += now_ms() - start;
+        self.profiler.push(ProfilePhase::Constraints, constraints_ms);
+
+        // integrating velocity also re-buckets each particle, so both are timed as the grid rebuild
+        let start = now_ms();
+        let (bounds, partition_size) = (self.config.bounds, self.config.spatial_partition_size);
+        let (swept, woken) = ccd::sweep(&mut self.particles, &self.spatial_partition, |distance| World::get_partition_reach(bounds, partition_size, distance), &self.config.species,
+                                        bounds, &self.obstacles, self.config.ccd, self.config.wall_damping, self.config.collision_damping,
+                                        self.config.friction, adjusted_dt);
+        // woken before the velocities are integrated, which would otherwise throw their impulse away
+        if !woken.is_empty() {
+            let reach = World::get_partition_reach(bounds, partition_size, 2.0 * self.config.species.max_radius());
+            sleep::wake_islands(&mut self.particles, &self.spatial_partition, reach, &self.config.species, &self.constraints, &self.rigid_bodies, woken);
+        }
         for (_, value) in self.spatial_partition.iter_mut() {
             value.clear();
         }
         //World::clear_spatial_partitions(&mut self.spatial_partition, self.config.bounds, self.config.spatial_partition_size);
 
-        let num_particles = self.particles.len();
-        for i in 0..num_particles {
-            let particle = &mut self.particles[i];
+        for (i, (particle, swept)) in self.particles.iter_mut().zip(swept).enumerate() {
             let particle_index = i as i32;
+            if swept {
+                World::rebucket(&mut self.spatial_partition, particle, particle_index, self.config.bounds, self.config.spatial_partition_size);
+                continue;
+            }
             World::apply_velocity(&mut self.spatial_partition, particle, particle_index, adjusted_dt, self.config.bounds, self.config.spatial_partition_size, self.config.friction);
         }
+        if sleeping {
+            let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, 2.0 * self.config.species.max_radius());
+            sleep::update_sleep(&mut self.particles, &self.spatial_partition, reach, &self.config.species, self.config.bounds, self.config.gravity_vector, &self.obstacles,
+                                &self.constraints, &self.rigid_bodies, self.config.sleep_speed, self.config.sleep_steps);
+        }
+        self.profiler.record(ProfilePhase::GridRebuild, start);
+    }
+
+    // Every constraint is a position correction solved over several substeps, velocities are derived from how far
+    // each particle actually moved. Springs become compliant distance constraints and their damping is unused.
+    fn step_xpbd(&mut self, dt: f32) {
+        let substeps = self.config.xpbd_substeps.max(1);
+        let substep_dt = dt / substeps as f32;
+        let mut previous_positions: Vec<Vec2f> = Vec::with_capacity(self.particles.len());
+        let contact_reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, 2.0 * self.config.species.max_radius());
+        let (mut bounds_ms, mut gravity_ms, mut overlap_ms, mut constraints_ms, mut grid_ms) = (0., 0., 0., 0., 0.);
+
+        let start = now_ms();
+        self.apply_forces(dt);
+        self.apply_thermal(dt);
+        self.profiler.record(ProfilePhase::Forces, start);
+
+        for _ in 0..substeps {
+            // contacts missed because of a stale partition would be corrected all at once later, which becomes velocity
+            let start = now_ms();
+            self.rebuild_spatial_partition();
+            grid_ms += now_ms() - start;
+
+            // overlap left over from earlier substeps (or from spawning particles on top of each other) is pushed out
+            // before the positions are recorded, so it never turns into velocity
+            let start = now_ms();
+            xpbd::solve_contacts(&mut self.particles, &self.spatial_partition, contact_reach, &self.config.species, 0., substep_dt);
+            xpbd::solve_bounds(&mut self.particles, self.config.bounds, &self.config.species, 0., substep_dt);
+            xpbd::solve_obstacles(&mut self.particles, &self.obstacles, &self.config.species, 0., substep_dt);
+            overlap_ms += now_ms() - start;
+
+            let start = now_ms();
+            World::apply_pressure(&mut self.particles, &self.soft_bodies, substep_dt);
+            previous_positions.clear();
+            for particle in self.particles.iter_mut() {
+                World::apply_gravity(particle, substep_dt, self.config.gravity_vector);
+                previous_positions.push(particle.world_position);
+                particle.world_position = particle.world_position.add(&particle.velocity.scale(substep_dt));
+            }
+            gravity_ms += now_ms() - start;
+
+            let start = now_ms();
+            xpbd::solve_contacts(&mut self.particles, &self.spatial_partition, contact_reach, &self.config.species, self.config.xpbd_contact_compliance, substep_dt);
+            overlap_ms += now_ms() - start;
+
+            let start = now_ms();
+            xpbd::solve_distances(&mut self.particles, &self.constraints, substep_dt);
+            for body in self.rigid_bodies.iter_mut() {
+                body.match_positions(&mut self.particles);
+            }
+            constraints_ms += now_ms() - start;
+
+            let start = now_ms();
+            xpbd::solve_bounds(&mut self.particles, self.config.bounds, &self.config.species, self.config.xpbd_boundary_compliance, substep_dt);
+            xpbd::solve_obstacles(&mut self.particles, &self.obstacles, &self.config.species, self.config.xpbd_boundary_compliance, substep_dt);
+            bounds_ms += now_ms() - start;
+
+            let start = now_ms();
+            for (particle, previous_position) in self.particles.iter_mut().zip(previous_positions.iter()) {
+                particle.velocity = particle.world_position.subtract(previous_position).scale(1. / substep_dt);
+            }
+            gravity_ms += now_ms() - start;
+        }
+
+        let start = now_ms();
+        self.constraints.retain(|constraint| !constraint.is_broken(&self.particles));
+        for body in self.rigid_bodies.iter_mut() {
+            body.update_velocity(&self.particles);
+        }
+        constraints_ms += now_ms() - start;
+
+        let start = now_ms();
+        self.conduct_heat(dt);
+        overlap_ms += now_ms() - start;
+
+        self.profiler.push(ProfilePhase::Bounds, bounds_ms);
+        self.profiler.push(ProfilePhase::Gravity, gravity_ms);
+        self.profiler.push(ProfilePhase::OverlapSolve, overlap_ms);
+        self.profiler.push(ProfilePhase::Constraints, constraints_ms);
+        self.profiler.push(ProfilePhase::GridRebuild, grid_ms);
+
+        for particle in self.particles.iter_mut() {
+            particle.velocity = particle.velocity.scale(self.config.friction);
+        }
+    }
+
+    // re-bucket every particle at its current position
+    fn rebuild_spatial_partition(&mut self) {
+        for (_, value) in self.spatial_partition.iter_mut() {
+            value.clear();
+        }
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            let partition = World::get_spatial_index(particle.world_position, self.config.bounds, self.config.spatial_partition_size);
+            particle.current_spatial_partition = partition;
+            self.spatial_partition.entry(partition).or_default().push(i as i32);
+        }
     }
 
     fn distance(vec1: Vec2f, vec2: Vec2f) -> f32 {
@@ -137,31 +539,105 @@ impl World {
         (dx * dx + dy * dy).sqrt()
     }
 
-    fn solve_overlap(particles: &mut Vec<Particle>, spatial_partition: &HashMap<(i32, i32), Vec<i32>>, particle_radius: f32) {
+    // pairwise forces between particles, everything beyond contact
+    fn apply_forces(&mut self, dt: f32) {
+        self.apply_interactions(dt);
+        self.apply_mutual_gravity(dt);
+        self.apply_electrostatics(dt);
+    }
+
+    fn apply_electrostatics(&mut self, dt: f32) {
+        let coulomb = &self.config.coulomb;
+        let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, coulomb.cutoff);
+        electrostatics::apply_coulomb(&mut self.particles, &self.spatial_partition, reach, coulomb, dt);
+        electrostatics::apply_external_fields(&mut self.particles, &self.obstacles, self.config.electric_field, coulomb, dt);
+    }
+
+    // inverse square attraction between every pair of (unit mass) particles, approximated with a Barnes-Hut tree
+    fn apply_mutual_gravity(&mut self, dt: f32) {
+        if !self.config.mutual_gravity {
+            return;
+        }
+        let positions: Vec<Vec2f> = self.particles.iter().map(|particle| particle.world_position).collect();
+        let masses = vec![1.0; positions.len()];
+        let tree = QuadTree::build(&positions, &masses);
+        for (index, particle) in self.particles.iter_mut().enumerate().filter(|(_, particle)| !particle.asleep) {
+            let field = tree.field_at(positions[index], index, self.config.barnes_hut_theta, self.config.gravity_softening);
+            particle.velocity = particle.velocity.add(&field.scale(self.config.gravitational_constant * dt));
+        }
+    }
+
+    // wall heating, buoyancy of hot particles and the kinetic thermostat
+    fn apply_thermal(&mut self, dt: f32) {
+        let config = &self.config;
+        thermal::apply_thermal_walls(&mut self.particles, &self.thermal_walls, config.bounds, &config.species, dt);
+        thermal::apply_buoyancy(&mut self.particles, config.gravity_vector, config.ambient_temperature, config.thermal_expansion, dt);
+        thermal::apply_thermostat(&mut self.particles, config.thermostat, config.thermostat_target, config.thermostat_tau, dt);
+    }
+
+    // contact conduction for the solvers that do not go through solve_overlap
+    fn conduct_heat(&mut self, dt: f32) {
+        let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, 2.0 * self.config.species.max_radius());
+        thermal::conduct(&mut self.particles, &self.spatial_partition, reach, &self.config.species, self.config.heat_conductivity, dt);
+    }
+
+    // species attraction and repulsion, skipped entirely while nothing in the matrix has a range
+    fn apply_interactions(&mut self, dt: f32) {
+        let range = self.config.species.max_range();
+        if range <= 0. {
+            return;
+        }
+        let reach = World::get_partition_reach(self.config.bounds, self.config.spatial_partition_size, range);
+        species::apply_interactions(&mut self.particles, &self.spatial_partition, reach, &self.config.species, dt);
+    }
+
+    // Sleeping particles are static, whatever touches them takes the whole correction. Returns the sleeping particles
+    // that were hit faster than `wake_speed`.
+    // `reach` has to cover the largest contact distance, partitions can be smaller than two radii of the largest species
+    fn solve_overlap(particles: &mut Vec<Particle>, spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable, heat_conductivity: f32, wake_speed: f32, dt: f32) -> Vec<usize> {
+        let mut woken = Vec::new();
         for a_index in 0..particles.len() {
+            if particles[a_index].asleep {
+                continue;
+            }
             let particle_a_pos = particles[a_index].world_position;
             let particle_a_vel = particles[a_index].velocity;
             let particle_a_spatial_coordinates = particles[a_index].current_spatial_partition;
 
-            for dx in -1..=1 {
-                for dy in -1..=1 {
+            for dx in -reach.0..=reach.0 {
+                for dy in -reach.1..=reach.1 {
                     if let Some(cell) = spatial_partition.get(&(particle_a_spatial_coordinates.0 + dx, particle_a_spatial_coordinates.1 + dy)) {
                         for neighbor in cell {
-                            if *neighbor != a_index as i32
+                            if *neighbor != a_index as i32 && !particles[a_index].shares_rigid_body(&particles[*neighbor as usize])
                             {
                                 let b_index: usize = *neighbor as usize;
                                 let particle_b_pos = particles[b_index].world_position;
                                 let particle_b_vel = particles[b_index].velocity;
                                 let distance = World::distance(particle_a_pos, particle_b_pos);
-                                if distance < 2.0 * particle_radius {
-                                    let overlap = (2.0 * particle_radius) - distance;
-                                    let direction = particle_a_pos.subtract(&particle_b_pos).normalized();
+                                let contact_distance = species.radius(particles[a_index].species) + species.radius(particles[b_index].species);
+                                if distance < contact_distance {
+                                    let overlap = contact_distance - distance;
+                                    // particles stacked exactly on top of each other (pinned in a corner) are split along an
+                                    // arbitrary axis, normalizing the zero vector would poison them and every rigid body they touch
+                                    let direction = if distance > f32::EPSILON { particle_a_pos.subtract(&particle_b_pos).normalized() } else { Vec2f::new(1., 0.) };
+
+                                    if particles[b_index].asleep {
+                                        particles[a_index].world_position = particle_a_pos.add(&direction.scale(overlap));
+                                        particles[a_index].velocity = particle_a_vel.add(&direction.scale(overlap));
+                                        if particle_a_vel.length() > wake_speed {
+                                            woken.push(b_index);
+                                        }
+                                        thermal::exchange_heat(particles, a_index, b_index, overlap, heat_conductivity, dt);
+                                        continue;
+                                    }
                 
                                     particles[a_index].world_position = particle_a_pos.add(&direction.scale(overlap / 2.0));
                                     particles[a_index].velocity = particle_a_vel.add(&direction.scale(overlap / 2.0));
                 
                                     particles[b_index].world_position = particle_b_pos.add(&direction.scale(-overlap / 2.0));
                                     particles[b_index].velocity = particle_b_vel.add(&direction.scale(-overlap / 2.0));
+
+                                    thermal::exchange_heat(particles, a_index, b_index, overlap, heat_conductivity, dt);
                                 }
                             }
                         }
@@ -169,6 +645,7 @@ impl World {
                 }
             }
         }
+        woken
     }
 
     fn solve_overlap_n2(particles: &mut Vec<Particle>, particle_radius: f32) {
@@ -198,6 +675,38 @@ impl World {
         }
     }
 
+    // linked particles sleep as a group, so a link with a sleeping end is asleep as a whole
+    fn apply_springs(particles: &mut [Particle], constraints: &[Constraint], dt: f32) {
+        for constraint in constraints {
+            if particles[constraint.a].asleep || particles[constraint.b].asleep {
+                continue;
+            }
+            constraint.apply_spring(particles, dt);
+        }
+    }
+
+    fn apply_pressure(particles: &mut [Particle], soft_bodies: &[SoftBody], dt: f32) {
+        for soft_body in soft_bodies {
+            if soft_body.ring.iter().any(|member| particles[*member].asleep) {
+                continue;
+            }
+            soft_body.apply_pressure(particles, dt);
+        }
+    }
+
+    // drop links that were stretched past their threshold, then project the rigid ones back to length
+    fn solve_constraints(particles: &mut [Particle], constraints: &mut Vec<Constraint>, iterations: usize) {
+        constraints.retain(|constraint| !constraint.is_broken(particles));
+        for _ in 0..iterations {
+            for constraint in constraints.iter() {
+                if particles[constraint.a].asleep || particles[constraint.b].asleep {
+                    continue;
+                }
+                constraint.solve_distance(particles);
+            }
+        }
+    }
+
     fn apply_gravity(particle: &mut Particle, dt: f32, gravity_vector: Vec2f) {
         let new_velocity = particle.velocity.add(&gravity_vector.scale(dt));
         particle.velocity = new_velocity;
@@ -205,6 +714,12 @@ impl World {
 
     // update the position and spatial partition of the particle, based on its current velocity. Also apply some friction.
     fn apply_velocity(spatial_partition: &mut HashMap<(i32, i32), Vec<i32>>,  particle: &mut Particle, particle_index: i32,  dt: f32, bounds: Vec2i, partition_radius: f32, friction: f32) {
+        // sleeping particles stay put but stay in the grid, so awake ones still collide with them
+        if particle.asleep {
+            particle.velocity = Vec2f::new(0., 0.);
+            World::rebucket(spatial_partition, particle, particle_index, bounds, partition_radius);
+            return;
+        }
 
         let current_velocity = particle.velocity;
         let current_position = particle.world_position;
@@ -219,6 +734,13 @@ impl World {
         spatial_partition.entry(new_partition).or_insert(Vec::new()).push(particle_index);
     }
 
+    // file the particle under the partition of its current position
+    fn rebucket(spatial_partition: &mut HashMap<(i32, i32), Vec<i32>>, particle: &mut Particle, particle_index: i32, bounds: Vec2i, partition_radius: f32) {
+        let partition = World::get_spatial_index(particle.world_position, bounds, partition_radius);
+        particle.current_spatial_partition = partition;
+        spatial_partition.entry(partition).or_default().push(particle_index);
+    }
+
     fn apply_bounds(particle: &mut Particle, bounds: Vec2i, wall_damping: f32, radius: f32) {
         let x = particle.world_position.x;
         let y = particle.world_position.y;
@@ -238,4 +760,54 @@ impl World {
             particle.world_position.y = if y <= min.y { min.y } else { max.y };
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // one particle in the middle of an empty world, nothing but its own velocity acting on it
+    fn moving_particle(speed: f32) -> World {
+        let mut world = World::new(ConfigState::new());
+        world.config.gravity_vector = Vec2f::new(0., 0.);
+        world.config.friction = 1.;
+        world.config.adaptive_timestep = true;
+        world.spawn_particle(Vec2f::new(400., 400.), Vec2f::new(speed, 0.));
+        world
+    }
+
+    #[test]
+    fn substeps_follow_the_cfl_limit() {
+        // half a radius of 5 per step at 100 per second is 0.025, and a frame of 0.04 is 0.12 of simulated time
+        let mut world = moving_particle(100.);
+        assert_eq!(world.cfl_timestep(), 0.025);
+        world.step(0.04);
+        assert_eq!(world.last_step_count, 5);
+        assert!((world.particles[0].world_position.x - 412.).abs() < 1e-3);
+
+        let mut resting = moving_particle(0.);
+        resting.step(0.04);
+        assert_eq!(resting.last_step_count, 1);
+
+        let mut fixed = moving_particle(100.);
+        fixed.config.adaptive_timestep = false;
+        fixed.step(0.04);
+        assert_eq!(fixed.last_step_count, 1);
+    }
+
+    #[test]
+    fn substeps_and_frame_time_are_capped() {
+        let mut fast = moving_particle(1000.);
+        fast.step(0.05);
+        assert_eq!(fast.last_step_count, fast.config.max_adaptive_steps);
+        // the time the capped steps could not cover is dropped, not squeezed into them,
+        // so the particle moves at most half a radius per step instead of the 150 of the whole frame
+        let moved = fast.particles[0].world_position.x - 400.;
+        assert!(moved > 15. && moved <= 20. + 1e-3, "{}", moved);
+
+        // a background tab coming back with a whole second only simulates the longest frame
+        let mut slow = moving_particle(10.);
+        slow.step(1.);
+        assert!((slow.particles[0].world_position.x - 403.).abs() < 1e-3);
+    }
+}
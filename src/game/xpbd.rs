@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use crate::game::constraint::{Constraint, ConstraintKind};
+use crate::game::obstacle::Obstacle;
+use crate::game::particle::Particle;
+use crate::game::species::SpeciesTable;
+use crate::math::{Vec2i, Vec2f};
+
+// Extended position based dynamics, solved with small substeps and a single pass per substep
+// (Macklin et al. 2019), so the lagrange multipliers start from zero every substep and never need storing.
+// Every particle has unit mass, compliance is the inverse stiffness and 0 means perfectly rigid.
+
+// correction magnitude for a constraint C with gradient norm 1 on `particle_count` unit mass particles
+fn delta_lambda(c: f32, particle_count: f32, compliance: f32, dt: f32) -> f32 {
+    let alpha_tilde = compliance / (dt * dt);
+    -c / (particle_count + alpha_tilde)
+}
+
+pub fn solve_contacts(particles: &mut [Particle], spatial_partition: &HashMap<(i32, i32), Vec<i32>>, reach: (i32, i32), species: &SpeciesTable, compliance: f32, dt: f32) {
+    for a_index in 0..particles.len() {
+        let (cell_x, cell_y) = particles[a_index].current_spatial_partition;
+        for dx in -reach.0..=reach.0 {
+            for dy in -reach.1..=reach.1 {
+                let Some(cell) = spatial_partition.get(&(cell_x + dx, cell_y + dy)) else { continue };
+                for neighbor in cell {
+                    let b_index = *neighbor as usize;
+                    // each pair once, members of the same rigid body never collide with each other
+                    if b_index <= a_index || particles[a_index].shares_rigid_body(&particles[b_index]) {
+                        continue;
+                    }
+                    let delta = particles[a_index].world_position.subtract(&particles[b_index].world_position);
+                    let distance = delta.length();
+                    let contact_distance = species.radius(particles[a_index].species) + species.radius(particles[b_index].species);
+                    if distance >= contact_distance {
+                        continue;
+                    }
+                    // particles sitting exactly on top of each other are split along an arbitrary axis
+                    let normal = if distance > f32::EPSILON { delta.scale(1. / distance) } else { Vec2f::new(1., 0.) };
+                    let correction = normal.scale(delta_lambda(distance - contact_distance, 2.0, compliance, dt));
+                    particles[a_index].world_position = particles[a_index].world_position.add(&correction);
+                    particles[b_index].world_position = particles[b_index].world_position.subtract(&correction);
+                }
+            }
+        }
+    }
+}
+
+pub fn solve_bounds(particles: &mut [Particle], bounds: Vec2i, species: &SpeciesTable, compliance: f32, dt: f32) {
+    for particle in particles.iter_mut() {
+        let particle_radius = species.radius(particle.species);
+        let min = Vec2f::new(particle_radius, particle_radius);
+        let max = Vec2f::new(bounds.x as f32 - particle_radius, bounds.y as f32 - particle_radius);
+        let position = &mut particle.world_position;
+        if position.x < min.x {
+            position.x += delta_lambda(position.x - min.x, 1.0, compliance, dt);
+        } else if position.x > max.x {
+            position.x -= delta_lambda(max.x - position.x, 1.0, compliance, dt);
+        }
+        if position.y < min.y {
+            position.y += delta_lambda(position.y - min.y, 1.0, compliance, dt);
+        } else if position.y > max.y {
+            position.y -= delta_lambda(max.y - position.y, 1.0, compliance, dt);
+        }
+    }
+}
+
+// rigid links have zero compliance, springs use the inverse of their stiffness
+pub fn solve_distances(particles: &mut [Particle], constraints: &[Constraint], dt: f32) {
+    for constraint in constraints {
+        let compliance = match constraint.kind {
+            ConstraintKind::Distance => 0.,
+            ConstraintKind::Spring { stiffness, .. } => if stiffness > 0. { 1. / stiffness } else { continue },
+        };
+        let delta = particles[constraint.a].world_position.subtract(&particles[constraint.b].world_position);
+        let length = delta.length();
+        if length <= f32::EPSILON {
+            continue;
+        }
+        let direction = delta.scale(1. / length);
+        let correction = direction.scale(delta_lambda(length - constraint.rest_length, 2.0, compliance, dt));
+        particles[constraint.a].world_position = particles[constraint.a].world_position.add(&correction);
+        particles[constraint.b].world_position = particles[constraint.b].world_position.subtract(&correction);
+    }
+}
+
+pub fn solve_obstacles(particles: &mut [Particle], obstacles: &[Obstacle], species: &SpeciesTable, compliance: f32, dt: f32) {
+    for particle in particles.iter_mut() {
+        for obstacle in obstacles {
+            let Some((normal, depth)) = obstacle.penetration(particle.world_position, species.radius(particle.species)) else { continue };
+            particle.world_position = particle.world_position.add(&normal.scale(delta_lambda(-depth, 1.0, compliance, dt)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vec4f;
+
+    const DT: f32 = 0.01;
+
+    fn species() -> SpeciesTable {
+        SpeciesTable::new(Vec4f::new(1., 1., 1., 1.), 5.)
+    }
+
+    fn at(positions: &[Vec2f]) -> Vec<Particle> {
+        positions.iter().map(|position| Particle::new(*position, Vec2f::new(0., 0.), (0, 0))).collect()
+    }
+
+    fn contact(mut particles: Vec<Particle>, compliance: f32) -> Vec<Particle> {
+        let spatial_partition = HashMap::from([((0, 0), (0..particles.len() as i32).collect())]);
+        solve_contacts(&mut particles, &spatial_partition, (1, 1), &species(), compliance, DT);
+        particles
+    }
+
+    #[test]
+    fn rigid_contacts_separate_in_one_pass() {
+        let particles = contact(at(&[Vec2f::new(100., 100.), Vec2f::new(106., 100.)]), 0.);
+        assert_eq!(particles[0].world_position, Vec2f::new(98., 100.));
+        assert_eq!(particles[1].world_position, Vec2f::new(108., 100.));
+
+        // no preferred direction for a pair on top of each other, but they still end up touching
+        let stacked = contact(at(&[Vec2f::new(100., 100.), Vec2f::new(100., 100.)]), 0.);
+        assert_eq!(stacked[1].world_position.subtract(&stacked[0].world_position).length(), 10.);
+    }
+
+    #[test]
+    fn compliance_softens_the_correction() {
+        // alpha / dt^2 = 2 matches the two unit masses, so half of the overlap is resolved
+        let particles = contact(at(&[Vec2f::new(100., 100.), Vec2f::new(106., 100.)]), 2. * DT * DT);
+        assert!((particles[1].world_position.x - particles[0].world_position.x - 8.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn separated_pairs_and_rigid_body_mates_are_left_alone() {
+        let apart = contact(at(&[Vec2f::new(100., 100.), Vec2f::new(111., 100.)]), 0.);
+        assert_eq!(apart[1].world_position, Vec2f::new(111., 100.));
+
+        let mut mates = at(&[Vec2f::new(100., 100.), Vec2f::new(106., 100.)]);
+        for particle in mates.iter_mut() {
+            particle.rigid_body = Some(0);
+        }
+        assert_eq!(contact(mates, 0.)[1].world_position, Vec2f::new(106., 100.));
+    }
+
+    #[test]
+    fn walls_push_particles_back_inside() {
+        let mut particles = at(&[Vec2f::new(2., 205.)]);
+        solve_bounds(&mut particles, Vec2i{ x: 100, y: 200 }, &species(), 0., DT);
+        assert_eq!(particles[0].world_position, Vec2f::new(5., 195.));
+    }
+}
@@ -1,53 +1,64 @@
 use wasm_bindgen::prelude::*;
-use web_sys::{WebGlRenderingContext, WebGlProgram};
+use web_sys::WebGlRenderingContext;
 extern crate js_sys;
 
-mod renderer;
-use renderer::{setup_vertices, Context};
+pub mod renderer;
+use renderer::{Context, Primitive, RenderBackend, Uniforms};
 
 mod math;
-use math::{Vec3f, Vec4f};
+use math::{Mat3f, Vec3f, Vec4f};
 
 mod shapes;
 use shapes::Triangle;
 
 mod util;
-use util::log;
+use util::{log, Error, LogLevel};
 
 mod game;
 use game::GameState;
+pub use game::headless::HeadlessSimulation;
 
+// messages below `level` are dropped, unless a target level says otherwise
 #[wasm_bindgen]
-pub fn create_game(canvas_id: &str) -> GameState {
+pub fn set_log_level(level: LogLevel) {
+    util::log::set_level(level);
+}
+
+// `target` is a module path like "crab_gl::game::world" and covers the modules under it
+#[wasm_bindgen]
+pub fn set_log_target_level(target: &str, level: LogLevel) {
+    util::log::set_target_level(target, level);
+}
+
+#[wasm_bindgen]
+pub fn clear_log_target_levels() {
+    util::log::clear_target_levels();
+}
+
+#[wasm_bindgen]
+pub fn create_game(canvas_id: &str) -> Result<GameState, Error> {
     GameState::new(canvas_id)
 }
 
 #[wasm_bindgen]
 pub fn draw_triangle(canvas_id: &str, selected_color: Option<Vec<f32>>) -> Result<WebGlRenderingContext, JsValue> {
-    let context = Context::new(canvas_id).unwrap();
-    let gl: &WebGlRenderingContext = context.get_gl_context();
-    let shader_program: &WebGlProgram = context.get_active_shader();
+    let mut context = Context::new_webgl(canvas_id)?;
 
     let top: Vec3f = Vec3f::new(0.0, 1.0, 0.0);
     let bottom_left: Vec3f = Vec3f::new(-1.0, -1.0, 0.0);
     let bottom_right: Vec3f = Vec3f::new(1.0, -1.0, 0.0);
     let triangle: Triangle = Triangle::new(top, bottom_left, bottom_right);
-    let vertices: [f32; 9] = triangle.as_float_array();
-    setup_vertices(&gl, &vertices, shader_program);
 
     let color_vec: Vec4f = selected_color.map_or(Vec4f::new(1.0, 0.0, 0.0, 1.0), |c| Vec4f::new(c[0], c[1], c[2], c[3]));
-    let color_location = gl
-        .get_uniform_location(shader_program, "fragColor")
-        .unwrap();
-    let floats: [f32; 4] = color_vec.as_float_array().clone();
-    gl.uniform4fv_with_f32_array(Some(&color_location), &floats);
-    log(&format!("Color: {:?}",floats), util::LogLevel::Warning);
-
-    gl.draw_arrays(
-        WebGlRenderingContext::TRIANGLES,
-        0,
-        (vertices.len() / 3) as i32,
-    );
-
-    Ok(gl.clone())
-}
\ No newline at end of file
+    log(&format!("Color: {:?}", color_vec.as_float_array()), util::LogLevel::Debug);
+
+    // through the backend, which also sets the scale and color attributes the shader expects. The triangle is already
+    // in clip coordinates, so the projection is the identity and the color goes in as the tint.
+    let backend = context.get_mutable_backend();
+    let mesh = backend.create_buffer()?;
+    backend.upload(mesh, &triangle.as_float_array());
+    backend.set_uniforms(&Uniforms { tint: color_vec, projection: Mat3f::IDENTITY });
+    backend.draw(Primitive::TriangleFan, mesh, 3);
+
+    Ok(context.get_gl_context().clone())
+}
@@ -0,0 +1,95 @@
+use crate::math::Vec2f;
+
+// 2D affine transform as a 3x3 matrix, column major like a GLSL mat3. Points are column vectors (x, y, 1).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3f {
+    pub columns: [[f32; 3]; 3],
+}
+
+impl Mat3f {
+    pub const IDENTITY: Mat3f = Mat3f { columns: [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]] };
+
+    pub fn translation(offset: Vec2f) -> Self {
+        Mat3f { columns: [[1., 0., 0.], [0., 1., 0.], [offset.x, offset.y, 1.]] }
+    }
+
+    pub fn scale(x: f32, y: f32) -> Self {
+        Mat3f { columns: [[x, 0., 0.], [0., y, 0.], [0., 0., 1.]] }
+    }
+
+    // counter clockwise, in radians
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Mat3f { columns: [[cos, sin, 0.], [-sin, cos, 0.], [0., 0., 1.]] }
+    }
+
+    // self * other, so `other` is applied first
+    pub fn multiply(&self, other: &Self) -> Self {
+        let mut columns = [[0.; 3]; 3];
+        for (column, other_column) in columns.iter_mut().zip(other.columns) {
+            for (row, value) in column.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.columns[k][row] * other_column[k]).sum();
+            }
+        }
+        Mat3f { columns }
+    }
+
+    pub fn transform_point(&self, point: Vec2f) -> Vec2f {
+        let [a, b, c] = self.columns;
+        Vec2f::new(a[0] * point.x + b[0] * point.y + c[0], a[1] * point.x + b[1] * point.y + c[1])
+    }
+
+    // None when the transform squashes the plane onto a line or a point
+    pub fn inverse(&self) -> Option<Self> {
+        let [a, b, c] = self.columns;
+        let determinant = a[0] * b[1] - b[0] * a[1];
+        if determinant == 0. || !determinant.is_finite() {
+            return None;
+        }
+        let linear = Mat3f { columns: [[b[1] / determinant, -a[1] / determinant, 0.], [-b[0] / determinant, a[0] / determinant, 0.], [0., 0., 1.]] };
+        let offset = linear.transform_point(Vec2f::new(c[0], c[1]));
+        Some(Mat3f::translation(offset.scale(-1.)).multiply(&linear))
+    }
+
+    pub fn as_float_array(&self) -> [f32; 9] {
+        let [a, b, c] = self.columns;
+        [a[0], a[1], a[2], b[0], b[1], b[2], c[0], c[1], c[2]]
+    }
+
+    // every column padded to four floats, how std140 lays out a mat3 in a uniform block
+    pub fn as_std140_array(&self) -> [f32; 12] {
+        let [a, b, c] = self.columns;
+        [a[0], a[1], a[2], 0., b[0], b[1], b[2], 0., c[0], c[1], c[2], 0.]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let transform = Mat3f::scale(2., 0.5).multiply(&Mat3f::rotation(1.1)).multiply(&Mat3f::translation(Vec2f::new(-3., 7.)));
+        let inverse = transform.inverse().unwrap();
+        for (column, identity) in inverse.multiply(&transform).columns.iter().zip(Mat3f::IDENTITY.columns) {
+            for (value, expected) in column.iter().zip(identity) {
+                assert!((value - expected).abs() < 1e-5, "{:?}", inverse.multiply(&transform));
+            }
+        }
+        let point = Vec2f::new(4., -9.);
+        let back = inverse.transform_point(transform.transform_point(point));
+        assert!(back.subtract(&point).length() < 1e-4);
+    }
+
+    #[test]
+    fn multiply_applies_the_right_hand_side_first() {
+        let moved_then_scaled = Mat3f::scale(2., 2.).multiply(&Mat3f::translation(Vec2f::new(1., 0.)));
+        assert_eq!(moved_then_scaled.transform_point(Vec2f::new(0., 0.)), Vec2f::new(2., 0.));
+    }
+
+    #[test]
+    fn squashed_transforms_have_no_inverse() {
+        assert_eq!(Mat3f::scale(0., 1.).inverse(), None);
+        assert_eq!(Mat3f::scale(f32::NAN, 1.).inverse(), None);
+    }
+}
@@ -10,5 +10,8 @@ pub use vec2f::Vec2f;
 pub mod vec2i;
 pub use vec2i::Vec2i;
 
+pub mod mat3f;
+pub use mat3f::Mat3f;
+
 pub mod random;
 pub use random::random_float;
\ No newline at end of file
@@ -0,0 +1,143 @@
+use crate::math::{Mat3f, Vec4f};
+use crate::util::Error;
+
+// Buffer owned by a backend, only meaningful to the backend that created it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferHandle(pub(crate) usize);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primitive {
+    // convex polygon, every vertex after the second closes a triangle with the first
+    TriangleFan,
+    // independent segments, two vertices each
+    Lines,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContextStatus {
+    Ready,
+    // the GL context is gone, nothing can be drawn
+    Lost,
+    // the GL context is back, RenderBackend::restore has to make the GL objects again before drawing
+    Restored,
+}
+
+// Rectangle of the drawing buffer clip space is mapped to, in pixels from the bottom left corner like glViewport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    pub fn full(width: u32, height: u32) -> Viewport {
+        Viewport { x: 0, y: 0, width: width as i32, height: height as i32 }
+    }
+
+    // The largest centered rectangle with `aspect` as its width over height, so content with that aspect is not
+    // stretched. The bars left over on two sides keep the clear color.
+    pub fn letterbox(width: u32, height: u32, aspect: f32) -> Viewport {
+        if !(aspect > 0. && aspect.is_finite()) || width == 0 || height == 0 {
+            return Viewport::full(width, height);
+        }
+        let fitted_width = (height as f32 * aspect).round().min(width as f32) as u32;
+        let fitted_height = (width as f32 / aspect).round().min(height as f32) as u32;
+        Viewport {
+            x: ((width - fitted_width) / 2) as i32,
+            y: ((height - fitted_height) / 2) as i32,
+            width: fitted_width as i32,
+            height: fitted_height as i32,
+        }
+    }
+}
+
+// Values shared by every vertex of a draw: the tint every color is multiplied by and the projection from world
+// units to clip space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uniforms {
+    pub tint: Vec4f,
+    pub projection: Mat3f,
+}
+
+// white and no projection, vertices are already in clip space
+impl Default for Uniforms {
+    fn default() -> Self {
+        Uniforms { tint: Vec4f::new(1., 1., 1., 1.), projection: Mat3f::IDENTITY }
+    }
+}
+
+// What Context needs from a graphics API. Buffers hold f32s: meshes are x, y, z per vertex in world units and
+// instance buffers are InstanceData laid out back to back. Instanced vertices are scaled and offset, then projected. Non instanced draws behave like a single instance at
+// the origin with scale 1 and a white color.
+pub trait RenderBackend {
+    fn create_buffer(&mut self) -> Result<BufferHandle, Error>;
+
+    // replaces the whole contents of the buffer
+    fn upload(&mut self, buffer: BufferHandle, data: &[f32]);
+
+    fn set_uniforms(&mut self, uniforms: &Uniforms);
+
+    // clears the whole drawing buffer, not only the viewport
+    fn clear(&mut self, color: Vec4f);
+
+    // drawing buffer size in pixels
+    fn size(&self) -> (u32, u32);
+
+    // Resizes the drawing buffer, its contents are gone until the next clear. The viewport covers all of it afterwards.
+    fn resize(&mut self, width: u32, height: u32);
+
+    fn set_viewport(&mut self, viewport: Viewport);
+
+    fn draw(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize);
+
+    // draws the first `vertex_count` vertices of the mesh once for each of the first `instance_count` instances
+    fn draw_instanced(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instances: BufferHandle, instance_count: usize);
+
+    // The drawing buffer size that matches how large the backend is shown, devicePixelRatio included. None for
+    // backends that are not shown on a page.
+    fn display_size(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    // whether the shown size changed since the last call
+    fn display_size_changed(&mut self) -> bool {
+        false
+    }
+
+    // backends that cannot lose their context are always ready
+    fn context_status(&self) -> ContextStatus {
+        ContextStatus::Ready
+    }
+
+    // Makes every GL object again after the context came back. Buffers keep their handles but lose their contents,
+    // the caller uploads them again.
+    fn restore(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn letterboxes_wide_and_tall_buffers() {
+        // bars left and right
+        assert_eq!(Viewport::letterbox(200, 100, 1.), Viewport { x: 50, y: 0, width: 100, height: 100 });
+        // bars top and bottom
+        assert_eq!(Viewport::letterbox(100, 300, 1.), Viewport { x: 0, y: 100, width: 100, height: 100 });
+        assert_eq!(Viewport::letterbox(1000, 1000, 16. / 9.), Viewport { x: 0, y: 218, width: 1000, height: 563 });
+        // already the right shape
+        assert_eq!(Viewport::letterbox(160, 90, 16. / 9.), Viewport::full(160, 90));
+    }
+
+    #[test]
+    fn meaningless_aspects_fill_the_buffer() {
+        for aspect in [0., -1., f32::NAN, f32::INFINITY] {
+            assert_eq!(Viewport::letterbox(200, 100, aspect), Viewport::full(200, 100));
+        }
+        assert_eq!(Viewport::letterbox(0, 100, 1.), Viewport::full(0, 100));
+    }
+}
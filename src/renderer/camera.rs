@@ -0,0 +1,150 @@
+use crate::math::{Mat3f, Vec2f};
+
+pub const DEFAULT_MIN_ZOOM: f32 = 0.1;
+pub const DEFAULT_MAX_ZOOM: f32 = 100.;
+
+// Which part of the world the viewport shows. At zoom 1 the viewport is `view_size` world units across, centered on
+// `position`, larger zooms show less of the world. Everything is drawn in world units and the projection maps them
+// to clip space in the vertex shader. Screen points are handled in clip coordinates, Context turns pixels into those.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera2D {
+    pub position: Vec2f, //world point at the center of the viewport
+    pub zoom: f32,
+    pub rotation: f32, //radians, counter clockwise
+    pub view_size: Vec2f, //world units across the viewport at zoom 1
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    drag_anchor: Option<Vec2f>, //world point held under the cursor while dragging
+}
+
+impl Camera2D {
+    pub fn new(position: Vec2f, view_size: Vec2f) -> Camera2D {
+        Camera2D {
+            position,
+            zoom: 1.,
+            rotation: 0.,
+            view_size,
+            min_zoom: DEFAULT_MIN_ZOOM,
+            max_zoom: DEFAULT_MAX_ZOOM,
+            drag_anchor: None,
+        }
+    }
+
+    // Shows the rectangle from the origin to `bounds`, the whole world when it is letterboxed to the same aspect
+    pub fn fit(bounds: Vec2f) -> Camera2D {
+        Camera2D::new(bounds.scale(0.5), bounds)
+    }
+
+    // world to clip space
+    pub fn projection(&self) -> Mat3f {
+        Mat3f::scale(2. * self.zoom / self.view_size.x, 2. * self.zoom / self.view_size.y)
+            .multiply(&Mat3f::rotation(-self.rotation))
+            .multiply(&Mat3f::translation(self.position.scale(-1.)))
+    }
+
+    pub fn world_to_clip(&self, world: Vec2f) -> Vec2f {
+        self.projection().transform_point(world)
+    }
+
+    // the projection only has no inverse for a zero zoom or view size, the camera position is the best answer then
+    pub fn clip_to_world(&self, clip: Vec2f) -> Vec2f {
+        self.projection().inverse().map_or(self.position, |inverse| inverse.transform_point(clip))
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom.clamp(self.min_zoom, self.max_zoom);
+    }
+
+    // Zooms by `factor`, greater than 1 zooms in, keeping the world point under `clip` where it is. Mouse wheel
+    // zoom, with the cursor as `clip`.
+    pub fn zoom_at(&mut self, clip: Vec2f, factor: f32) {
+        let anchor = self.clip_to_world(clip);
+        self.set_zoom(self.zoom * factor);
+        self.position = self.position.add(&anchor.subtract(&self.clip_to_world(clip)));
+    }
+
+    // Drag panning: the world point under `clip` when the drag starts stays under the cursor as it moves
+    pub fn begin_drag(&mut self, clip: Vec2f) {
+        self.drag_anchor = Some(self.clip_to_world(clip));
+    }
+
+    pub fn drag_to(&mut self, clip: Vec2f) {
+        if let Some(anchor) = self.drag_anchor {
+            self.position = self.position.add(&anchor.subtract(&self.clip_to_world(clip)));
+        }
+    }
+
+    pub fn end_drag(&mut self) {
+        self.drag_anchor = None;
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag_anchor.is_some()
+    }
+}
+
+// clip space itself, for drawing in gl coordinates
+impl Default for Camera2D {
+    fn default() -> Self {
+        Camera2D::new(Vec2f::new(0., 0.), Vec2f::new(2., 2.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: Vec2f, expected: Vec2f) {
+        assert!(actual.subtract(&expected).length() < 1e-3, "{:?} != {:?}", actual, expected);
+    }
+
+    fn turned_camera() -> Camera2D {
+        let mut camera = Camera2D::new(Vec2f::new(30., -20.), Vec2f::new(100., 50.));
+        camera.set_zoom(2.5);
+        camera.rotation = 0.7;
+        camera
+    }
+
+    #[test]
+    fn world_and_clip_round_trip() {
+        let camera = turned_camera();
+        for world in [Vec2f::new(0., 0.), Vec2f::new(30., -20.), Vec2f::new(-45., 12.5), Vec2f::new(400., 300.)] {
+            assert_near(camera.clip_to_world(camera.world_to_clip(world)), world);
+        }
+        assert_near(camera.world_to_clip(camera.position), Vec2f::new(0., 0.));
+    }
+
+    #[test]
+    fn fit_maps_the_bounds_to_the_clip_square() {
+        let camera = Camera2D::fit(Vec2f::new(800., 600.));
+        assert_near(camera.world_to_clip(Vec2f::new(0., 0.)), Vec2f::new(-1., -1.));
+        assert_near(camera.world_to_clip(Vec2f::new(800., 600.)), Vec2f::new(1., 1.));
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_cursor() {
+        let mut camera = turned_camera();
+        let cursor = Vec2f::new(0.4, -0.6);
+        let anchor = camera.clip_to_world(cursor);
+        camera.zoom_at(cursor, 3.);
+        assert_eq!(camera.zoom, 7.5);
+        assert_near(camera.clip_to_world(cursor), anchor);
+        // clamped at the limit, the anchor still holds
+        camera.zoom_at(cursor, 1000.);
+        assert_eq!(camera.zoom, DEFAULT_MAX_ZOOM);
+        assert_near(camera.clip_to_world(cursor), anchor);
+    }
+
+    #[test]
+    fn dragging_keeps_the_grabbed_point_under_the_cursor() {
+        let mut camera = turned_camera();
+        let grabbed = camera.clip_to_world(Vec2f::new(-0.2, 0.3));
+        camera.begin_drag(Vec2f::new(-0.2, 0.3));
+        camera.drag_to(Vec2f::new(0.5, -0.1));
+        assert_near(camera.clip_to_world(Vec2f::new(0.5, -0.1)), grabbed);
+        camera.end_drag();
+        let position = camera.position;
+        camera.drag_to(Vec2f::new(0.9, 0.9));
+        assert_eq!(camera.position, position);
+    }
+}
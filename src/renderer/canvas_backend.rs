@@ -0,0 +1,96 @@
+use web_sys::HtmlCanvasElement;
+use crate::math::Vec4f;
+use crate::renderer::backend::{BufferHandle, ContextStatus, Primitive, RenderBackend, Uniforms, Viewport};
+use crate::renderer::{WebGlBackend, WebGl2Backend};
+use crate::util::log::{log_target, LogLevel};
+use crate::util::Error;
+
+// The browser backend, WebGL2 where the browser has it and WebGL1 with ANGLE_instanced_arrays everywhere else.
+pub enum CanvasBackend {
+    WebGl2(WebGl2Backend),
+    WebGl(WebGlBackend),
+}
+
+impl CanvasBackend {
+    // A canvas hands out one kind of context for its whole life, so the choice is made once: when WebGL2 is
+    // available it is used, even if setting it up fails afterwards.
+    pub fn new(canvas: HtmlCanvasElement) -> Result<CanvasBackend, Error> {
+        if let Some(context) = WebGl2Backend::get_rendering_context(&canvas) {
+            return Ok(CanvasBackend::WebGl2(WebGl2Backend::new(canvas, context)?));
+        }
+        log_target(module_path!(), "WebGL2 is not available, falling back to WebGL1", LogLevel::Warn);
+        Ok(CanvasBackend::WebGl(WebGlBackend::new(canvas)?))
+    }
+
+    pub fn is_webgl2(&self) -> bool {
+        matches!(self, CanvasBackend::WebGl2(_))
+    }
+
+    fn backend_ref(&self) -> &dyn RenderBackend {
+        match self {
+            CanvasBackend::WebGl2(backend) => backend,
+            CanvasBackend::WebGl(backend) => backend,
+        }
+    }
+
+    fn backend(&mut self) -> &mut dyn RenderBackend {
+        match self {
+            CanvasBackend::WebGl2(backend) => backend,
+            CanvasBackend::WebGl(backend) => backend,
+        }
+    }
+}
+
+impl RenderBackend for CanvasBackend {
+    fn create_buffer(&mut self) -> Result<BufferHandle, Error> {
+        self.backend().create_buffer()
+    }
+
+    fn upload(&mut self, buffer: BufferHandle, data: &[f32]) {
+        self.backend().upload(buffer, data)
+    }
+
+    fn set_uniforms(&mut self, uniforms: &Uniforms) {
+        self.backend().set_uniforms(uniforms)
+    }
+
+    fn clear(&mut self, color: Vec4f) {
+        self.backend().clear(color)
+    }
+
+    fn size(&self) -> (u32, u32) {
+        self.backend_ref().size()
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.backend().resize(width, height)
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.backend().set_viewport(viewport)
+    }
+
+    fn display_size(&self) -> Option<(u32, u32)> {
+        self.backend_ref().display_size()
+    }
+
+    fn display_size_changed(&mut self) -> bool {
+        self.backend().display_size_changed()
+    }
+
+    fn context_status(&self) -> ContextStatus {
+        self.backend_ref().context_status()
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        self.backend().restore()
+    }
+
+    fn draw(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize) {
+        self.backend().draw(primitive, mesh, vertex_count)
+    }
+
+    fn draw_instanced(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instances: BufferHandle, instance_count: usize) {
+        self.backend().draw_instanced(primitive, mesh, vertex_count, instances, instance_count)
+    }
+}
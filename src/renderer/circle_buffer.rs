@@ -1,45 +1,61 @@
-use web_sys::{WebGlRenderingContext, WebGlProgram, WebGlBuffer};
 use crate::game;
-use crate::game::world::world_to_gl;
-use crate::math::{Vec3f, Vec2f};
-use crate::renderer::context::AngleInstancedArrays;
-use crate::util::log;
+use crate::math::Vec3f;
+use crate::renderer::backend::{BufferHandle, Primitive, RenderBackend};
+use crate::util::Error;
 
 pub struct VertexData{
     pub position: Vec3f
 }
 
 #[derive(Copy, Clone)]
+#[repr(C)]
 pub struct InstanceData{
     pub center_x: f32,
     pub center_y: f32,
+    pub scale: f32,
+    pub color: [f32; 4],
 }
 
-#[allow(unused)]
+impl InstanceData{
+    pub fn new(center_x: f32, center_y: f32) -> InstanceData {
+        InstanceData{ center_x, center_y, scale: 1.0, color: [1.0, 1.0, 1.0, 1.0] }
+    }
+}
+
+pub const INSTANCE_FLOATS: usize = std::mem::size_of::<InstanceData>() / std::mem::size_of::<f32>();
+pub const INSTANCE_STRIDE: i32 = std::mem::size_of::<InstanceData>() as i32;
+pub const SCALE_OFFSET: i32 = 2 * std::mem::size_of::<f32>() as i32;
+pub const COLOR_OFFSET: i32 = 3 * std::mem::size_of::<f32>() as i32;
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+// One circle mesh drawn once per instance, the instances are re-uploaded every frame.
 pub struct CircleBuffer{
     pub vertices: Vec<VertexData>,
-    vertex_buffer: WebGlBuffer,
+    vertex_buffer: BufferHandle,
     pub instances: Vec<InstanceData>,
-    instance_array: WebGlBuffer
+    instance_array: BufferHandle,
 }
 
 impl CircleBuffer{
-    pub fn new(gl: &WebGlRenderingContext, shader_program: &WebGlProgram, ext: &AngleInstancedArrays) -> CircleBuffer {
-        //static vertices for the circle
-        let a = world_to_gl(game::game_state::DEFAULT_BOUNDS, Vec2f::new(0., 0.));
-        let b = world_to_gl(game::game_state::DEFAULT_BOUNDS, Vec2f::new(game::game_state::DEFAULT_PARTICLE_RADIUS, 0.));
-        let radius = (b.subtract(&a)).length();
-        let vertices = CircleBuffer::generate_circle_points(Vec3f::new(0., 0., 0.), radius, 32);
-        let vertex_buffer = CircleBuffer::new_vertex_array(gl, &vertices, shader_program);
+    pub fn new(backend: &mut impl RenderBackend) -> Result<CircleBuffer, Error> {
+        //static vertices for the circle, in world units
+        let radius = game::game_state::DEFAULT_PARTICLE_RADIUS;
+        let vertices = CircleBuffer::generate_circle_points(Vec3f::new(0., 0., 0.), radius, CIRCLE_SEGMENTS);
+        let vertex_buffer = backend.create_buffer()?;
 
         //buffer for the instance data that will change every frame
-        let instances: Vec<InstanceData> = vec![InstanceData{center_x: 0.0, center_y: 0.0}; game::game_state::DEFAULT_MAX_PARTICLES];
-        let instance_array = CircleBuffer::new_instance_array(gl, CircleBuffer::get_buffer_ref(&instances), shader_program, ext);
-        return CircleBuffer{vertices,
-                            vertex_buffer,
-                            instances,
-                            instance_array,
-                            };
+        let instances: Vec<InstanceData> = vec![InstanceData::new(0.0, 0.0); game::game_state::DEFAULT_MAX_PARTICLES];
+        let instance_array = backend.create_buffer()?;
+        backend.upload(instance_array, CircleBuffer::get_buffer_ref(&instances));
+        let circle_buffer = CircleBuffer{ vertices, vertex_buffer, instances, instance_array };
+        circle_buffer.upload_mesh(backend);
+        Ok(circle_buffer)
+    }
+
+    // the mesh is only uploaded once, and again after the backend lost its buffers
+    pub fn upload_mesh(&self, backend: &mut impl RenderBackend) {
+        backend.upload(self.vertex_buffer, CircleBuffer::get_buffer_ref(&self.vertices));
     }
 
     // cast Vec<T> -> &[f32]
@@ -63,69 +79,15 @@ impl CircleBuffer{
         points
     }
 
-    fn new_vertex_array(gl: &WebGlRenderingContext, vertices: &Vec<VertexData>, shader_program: &WebGlProgram) -> WebGlBuffer {
-        // Create the OpenGL bufer and get a handle
-        let id: WebGlBuffer = gl.create_buffer().unwrap();
-
-        // Bind buffer
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&id));
-
-        // Allocate initial memory + copy data
-        let vertex_array = unsafe { js_sys::Float32Array::view(CircleBuffer::get_buffer_ref(vertices)) };
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &vertex_array,
-            WebGlRenderingContext::STATIC_DRAW,
-        );
-
-        // Enable the position attribute
-        let position_attrib = gl.get_attrib_location(&shader_program, "position");
-        gl.vertex_attrib_pointer_with_i32(
-            position_attrib as u32,
-            3,
-            WebGlRenderingContext::FLOAT,
-            false,
-            0,
-            0,
-        );
-        gl.enable_vertex_attrib_array(position_attrib as u32);
-
-        return id;
-    }
-
-    fn new_instance_array(gl: &WebGlRenderingContext, vertices: &[f32], shader_program: &WebGlProgram, ext: &AngleInstancedArrays) -> WebGlBuffer {
-        // Create the OpenGL bufer and get a handle
-        let id: WebGlBuffer = gl.create_buffer().unwrap();
-
-        // Bind buffer
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&id));
-
-        // Allocate initial memory + copy data
-        let instance_array = unsafe { js_sys::Float32Array::view(&vertices) };
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &instance_array,
-            WebGlRenderingContext::DYNAMIC_DRAW,
-        );
-
-        let offset_attrib = gl.get_attrib_location(&shader_program, "offset") as u32;
-        gl.enable_vertex_attrib_array(offset_attrib);
-        gl.vertex_attrib_pointer_with_i32(offset_attrib, 2, WebGlRenderingContext::FLOAT, false, 0, 0);
-        ext.vertex_attrib_divisor_angle(offset_attrib, 1);
-        return id;
+    pub fn get_mutable_instances(&mut self) -> &mut Vec<InstanceData>{
+        &mut self.instances
     }
 
-    pub fn get_mutable_instances(&mut self) -> &mut Vec<InstanceData>{
-        return &mut self.instances;
+    pub fn buffer_instances(&self, backend: &mut impl RenderBackend){
+        backend.upload(self.instance_array, CircleBuffer::get_buffer_ref(&self.instances));
     }
 
-    pub fn buffer_instances(&self, gl: &WebGlRenderingContext){
-        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.instance_array));
-        let instances_array = unsafe { js_sys::Float32Array::view(CircleBuffer::get_buffer_ref(&self.instances)) };
-        gl.buffer_data_with_array_buffer_view(
-            WebGlRenderingContext::ARRAY_BUFFER,
-            &instances_array,
-            WebGlRenderingContext::DYNAMIC_DRAW,
-        );
+    pub fn draw(&self, backend: &mut impl RenderBackend) {
+        backend.draw_instanced(Primitive::TriangleFan, self.vertex_buffer, self.vertices.len(), self.instance_array, self.instances.len());
     }
-}
\ No newline at end of file
+}
@@ -1,120 +1,289 @@
-use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::WebGl2RenderingContext;
 use web_sys::{WebGlRenderingContext, WebGlProgram, Document, HtmlCanvasElement};
-use wasm_bindgen::JsValue;
-use crate::renderer::setup_shaders;
-use crate::math::Vec4f;
+use crate::math::{Vec2f, Vec4f};
+use crate::renderer::backend::{ContextStatus, RenderBackend, Uniforms, Viewport};
 use crate::renderer::circle_buffer::InstanceData;
+use crate::renderer::{Camera2D, CanvasBackend, SoftwareBackend, WebGlBackend};
+use crate::util::profiler::{now_ms, ProfilePhase, Profiler};
+use crate::util::Error;
+use crate::util::log::{log_target, log_throttled, LogLevel};
 
-use super::CircleBuffer;
+use super::{CircleBuffer, LineBuffer};
 
-#[wasm_bindgen]
-extern "C" {
-    #[wasm_bindgen(js_name = ANGLEInstancedArrays)]
-    pub type AngleInstancedArrays;
+pub const WHITE: Vec4f = Vec4f{ x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
+pub const LINK_COLOR: Vec4f = Vec4f{ x: 0.9, y: 0.9, z: 0.9, w: 1.0 };
+pub const BACKGROUND_COLOR: Vec4f = Vec4f{ x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
 
-    #[wasm_bindgen(method, getter, js_name = VERTEX_ATTRIB_ARRAY_DIVISOR_ANGLE)]
-    pub fn vertex_attrib_array_divisor_angle(this: &AngleInstancedArrays) -> i32;
+// What the viewer draws each frame, particle circles and link lines, independent of where it is drawn to.
+pub struct Context<B: RenderBackend = CanvasBackend>{
+    backend: B,
+    buffer: CircleBuffer,
+    lines: LineBuffer,
+    profiler: Profiler,
+    aspect: Option<f32>, //width over height of what is drawn, kept by letterboxing. None stretches it over the canvas
+    viewport: Viewport,
+    auto_resize: bool, //follow the shown size of the canvas
+    camera: Camera2D,
+}
 
-    #[wasm_bindgen(method, catch, js_name = drawArraysInstancedANGLE)]
-    pub fn draw_arrays_instanced_angle(this: &AngleInstancedArrays, mode: u32, first: i32, count: i32, primcount: i32) -> Result<(), JsValue>;
+impl Context<CanvasBackend> {
+    // draws into the canvas with WebGL2, or WebGL1 where the browser has no WebGL2
+    pub fn new(canvas_id: &str) -> Result<Context, Error> {
+        let canvas = get_canvas(&get_document()?, canvas_id)?;
+        let backend = CanvasBackend::new(canvas)?;
+        Context::with_backend(backend)
+    }
+}
 
-    // TODO offset should be i64
-    #[wasm_bindgen(method, catch, js_name = drawElementsInstancedANGLE)]
-    pub fn draw_elements_instanced_angle(this: &AngleInstancedArrays, mode: u32, count: i32, type_: u32, offset: i32, primcount: i32) -> Result<(), JsValue>;
+impl Context<WebGlBackend> {
+    // always WebGL1, for callers that draw with the gl context themselves
+    pub fn new_webgl(canvas_id: &str) -> Result<Context<WebGlBackend>, Error> {
+        let canvas = get_canvas(&get_document()?, canvas_id)?;
+        let backend = WebGlBackend::new(canvas)?;
+        Context::with_backend(backend)
+    }
 
-    #[wasm_bindgen(method, js_name = vertexAttribDivisorANGLE)]
-    pub fn vertex_attrib_divisor_angle(this: &AngleInstancedArrays, index: u32, divisor: u32);
+    pub fn get_gl_context(&self) -> &WebGlRenderingContext{
+        self.backend.get_gl_context()
+    }
+
+    pub fn get_active_shader(&self) -> &WebGlProgram{
+        self.backend.get_active_shader()
+    }
 }
 
-#[allow(unused)]
-pub struct Context{
-    canvas_id: String,
-    document: Document,
-    canvas: HtmlCanvasElement,
-    context: WebGlRenderingContext,
-    active_shader: WebGlProgram,
-    ext: AngleInstancedArrays,
-    buffer: CircleBuffer,
-    current_color: Vec4f
+impl Context<SoftwareBackend> {
+    // draws into a width x height image in memory instead of a canvas
+    pub fn headless(width: u32, height: u32) -> Result<Context<SoftwareBackend>, Error> {
+        Context::with_backend(SoftwareBackend::new(width, height))
+    }
 }
 
-impl Context {
-    pub fn new(canvas_id: &str) -> Result<Context, JsValue> {
-        let canvas_id: String = canvas_id.to_string();
-        let document = Self::get_document();
-        let canvas = Self::get_canvas(&document, &canvas_id);
-        let context = Self::get_rendering_context(&canvas);
-        let active_shader = setup_shaders(&context).unwrap();
-        let ext = Self::get_angle_extension(&context);
-        let buffer = CircleBuffer::new(&context, &active_shader, &ext);
-        let current_color = Vec4f::new(0.5, 0.7, 0.4, 1.0);
-        Context::set_color(&context, &active_shader, &current_color);
-        context.viewport(
-            0,
-            0,
-            canvas.width().try_into().unwrap(),
-            canvas.height().try_into().unwrap(),
-        );
-
-        return Ok(Context{
-            canvas_id,
-            document,
-            canvas,
-            context,
-            active_shader,
-            ext,
+impl<B: RenderBackend> Context<B> {
+    pub fn with_backend(mut backend: B) -> Result<Context<B>, Error> {
+        let buffer = CircleBuffer::new(&mut backend)?;
+        let lines = LineBuffer::new(&mut backend)?;
+        let (width, height) = backend.size();
+        Ok(Context{
+            backend,
             buffer,
-            current_color
-        });
+            lines,
+            profiler: Profiler::new(),
+            aspect: None,
+            viewport: Viewport::full(width, height),
+            auto_resize: false,
+            camera: Camera2D::default(),
+        })
     }
 
-    pub fn get_gl_context(&self) -> &WebGlRenderingContext{
-        return &self.context;
+    // drawing buffer size in device pixels, the viewport is fitted to it again
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.backend.resize(width.max(1), height.max(1));
+        self.update_viewport();
     }
 
-    pub fn get_active_shader(&self) -> &WebGlProgram{
-        return &self.active_shader;
+    // Keeps the drawing buffer at the size the canvas is shown at from now on, checked before every frame. Does
+    // nothing for backends that are not shown on a page.
+    pub fn set_auto_resize(&mut self, enabled: bool) {
+        self.auto_resize = enabled;
+        if enabled {
+            self.fit_to_display();
+        }
+    }
+
+    pub fn set_aspect(&mut self, aspect: Option<f32>) {
+        self.aspect = aspect;
+        self.update_viewport();
+    }
+
+    pub fn get_viewport(&self) -> Viewport {
+        self.viewport
+    }
+
+    pub fn get_camera(&self) -> &Camera2D {
+        &self.camera
+    }
+
+    pub fn get_mutable_camera(&mut self) -> &mut Camera2D {
+        &mut self.camera
+    }
+
+    // A point in drawing buffer pixels from the top left corner, like a mouse position scaled by devicePixelRatio, in
+    // clip coordinates of the viewport. Points in the letterbox bars end up outside -1 to 1.
+    pub fn screen_to_clip(&self, x: f32, y: f32) -> Vec2f {
+        let (_, height) = self.backend.size();
+        let viewport = &self.viewport;
+        let clip_x = (x - viewport.x as f32) / viewport.width.max(1) as f32 * 2. - 1.;
+        let clip_y = (height as f32 - y - viewport.y as f32) / viewport.height.max(1) as f32 * 2. - 1.;
+        Vec2f::new(clip_x, clip_y)
+    }
+
+    pub fn screen_to_world(&self, x: f32, y: f32) -> Vec2f {
+        self.camera.clip_to_world(self.screen_to_clip(x, y))
     }
 
-    pub fn set_color(context: &WebGlRenderingContext, shader: &WebGlProgram, color: &Vec4f) {
-        let color_location = context
-            .get_uniform_location(&shader, "fragColor")
-            .unwrap();
-        context.uniform4fv_with_f32_array(Some(&color_location), &color.as_float_array());
+    fn fit_to_display(&mut self) {
+        if let Some((width, height)) = self.backend.display_size() {
+            if (width, height) != self.backend.size() {
+                self.resize(width, height);
+            }
+        }
     }
 
-    fn get_document() -> Document {
-        return web_sys::window().unwrap().document().unwrap();
+    fn update_viewport(&mut self) {
+        let (width, height) = self.backend.size();
+        self.viewport = match self.aspect {
+            Some(aspect) => Viewport::letterbox(width, height, aspect),
+            None => Viewport::full(width, height),
+        };
+        self.backend.set_viewport(self.viewport);
     }
 
-    fn get_canvas(document: &Document, canvas_id: &str) -> HtmlCanvasElement {
-        return document.get_element_by_id(canvas_id).unwrap().dyn_into::<web_sys::HtmlCanvasElement>().unwrap();
+    // Skips the frame while the backend has lost its context, the simulation keeps going without it. Once the context
+    // is back the shaders and buffers are made again from what is kept here.
+    pub fn dispatch_draw(&mut self){
+        match self.backend.context_status() {
+            ContextStatus::Ready => {}
+            ContextStatus::Lost => return,
+            ContextStatus::Restored => {
+                if let Err(error) = self.restore() {
+                    log_throttled(module_path!(), &format!("Failed to restore the WebGL context: {}", error), LogLevel::Error, 1000.);
+                    return;
+                }
+            }
+        }
+        if self.auto_resize && self.backend.display_size_changed() {
+            self.fit_to_display();
+        }
+        self.backend.clear(BACKGROUND_COLOR);
+
+        let start = now_ms();
+        self.buffer.buffer_instances(&mut self.backend);
+        self.profiler.record(ProfilePhase::InstanceUpload, start);
+
+        let start = now_ms();
+        // circles carry their species color per instance, the uniform only tints
+        let projection = self.camera.projection();
+        self.backend.set_uniforms(&Uniforms{ tint: WHITE, projection });
+        self.buffer.draw(&mut self.backend);
+
+        self.backend.set_uniforms(&Uniforms{ tint: LINK_COLOR, projection });
+        self.lines.draw(&mut self.backend);
+        self.profiler.record(ProfilePhase::Draw, start);
     }
 
-    fn get_rendering_context(canvas: &HtmlCanvasElement) -> WebGlRenderingContext {
-        let gl: WebGlRenderingContext = canvas
-            .get_context("webgl").unwrap()
-            .unwrap()
-            .dyn_into::<WebGlRenderingContext>().unwrap();
-        return gl;
+    fn restore(&mut self) -> Result<(), Error> {
+        self.backend.restore()?;
+        // instances and lines are uploaded every frame anyway, only the mesh needs to go up again
+        self.buffer.upload_mesh(&mut self.backend);
+        self.backend.set_viewport(self.viewport);
+        log_target(module_path!(), "WebGL context restored", LogLevel::Info);
+        Ok(())
     }
 
-    fn get_angle_extension(gl: &WebGlRenderingContext) -> AngleInstancedArrays {
-        let extension: js_sys::Object = gl.get_extension("ANGLE_instanced_arrays").expect("Unable to find ANGLE_instanced_arrays").unwrap();
-        let ext: AngleInstancedArrays = extension.unchecked_into::<AngleInstancedArrays>();
-        return ext;
+    pub fn is_context_lost(&self) -> bool {
+        self.backend.context_status() != ContextStatus::Ready
     }
 
-    pub fn dispatch_draw(&self){
-        self.context.clear_color(0.0, 0.0, 0.0, 1.0);
-        self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
-        self.buffer.buffer_instances(&self.context);
-        self.ext.draw_arrays_instanced_angle(WebGlRenderingContext::TRIANGLE_FAN, 0, 32, self.buffer.instances.len() as i32).expect("Failed to draw circles");
+    pub fn get_backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn get_mutable_backend(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    pub fn get_mutable_lines(&mut self) -> &mut LineBuffer{
+        &mut self.lines
+    }
+
+    pub fn get_profiler(&self) -> &Profiler {
+        &self.profiler
     }
 
     pub fn get_mutable_instances(&mut self) -> &mut Vec<InstanceData>{
-        return self.buffer.get_mutable_instances();
+        self.buffer.get_mutable_instances()
+    }
+}
+
+fn get_document() -> Result<Document, Error> {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or_else(|| Error::Canvas("no document to find the canvas in".into()))
+}
+
+fn get_canvas(document: &Document, canvas_id: &str) -> Result<HtmlCanvasElement, Error> {
+    document.get_element_by_id(canvas_id)
+        .ok_or_else(|| Error::Canvas(format!("no element with id '{}'", canvas_id)))?
+        .dyn_into::<web_sys::HtmlCanvasElement>()
+        .map_err(|_| Error::Canvas(format!("element '{}' is not a canvas", canvas_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // checked in, regenerate with UPDATE_GOLDEN=1 cargo test after a deliberate change to the rasterizer
+    const GOLDEN_SCENE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/renderer/testdata/headless_scene.png");
+
+    // A 100 x 100 world letterboxed into 200 x 100 pixels, so one world unit is one pixel and the world spans
+    // columns 50 to 150. One red circle at (50, 50) and one link along y = 10.5, the middle of pixel row 89.
+    fn draw_scene() -> Context<SoftwareBackend> {
+        let mut context = Context::headless(200, 100).unwrap();
+        context.set_aspect(Some(1.));
+        *context.get_mutable_camera() = Camera2D::fit(Vec2f::new(100., 100.));
+        let instances = context.get_mutable_instances();
+        instances.clear();
+        instances.push(InstanceData { color: [1., 0., 0., 1.], ..InstanceData::new(50., 50.) });
+        context.get_mutable_lines().push_line(Vec2f::new(10., 10.5), Vec2f::new(90., 10.5));
+        context.dispatch_draw();
+        context
+    }
+
+    #[test]
+    fn draws_circle_line_and_letterbox() {
+        let context = draw_scene();
+        let image = context.get_backend();
+        assert_eq!(context.get_viewport(), Viewport { x: 50, y: 0, width: 100, height: 100 });
+        assert_eq!(image.pixel(100, 50), [255, 0, 0, 255]);
+        // outside the circle and off the line
+        assert_eq!(image.pixel(100, 30), [0, 0, 0, 255]);
+        // the line is unbroken, only its antialiased ends at columns 59 and 140 are partially covered
+        for x in 60..140 {
+            assert_eq!(image.pixel(x, 89), [230, 230, 230, 255], "line pixel {}", x);
+        }
+        // the bars stay the clear color, the line stops at the world edge
+        for y in 0..100 {
+            for x in (0..50).chain(150..200) {
+                assert_eq!(image.pixel(x, y), [0, 0, 0, 255], "letterbox pixel {} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn screen_points_map_through_the_letterbox() {
+        let context = draw_scene();
+        // corners of the viewport, with the screen y running down
+        assert_eq!(context.screen_to_clip(50., 100.), Vec2f::new(-1., -1.));
+        assert_eq!(context.screen_to_clip(150., 0.), Vec2f::new(1., 1.));
+        // the bars are outside clip space
+        assert!(context.screen_to_clip(25., 50.).x < -1.);
+        let world = context.screen_to_world(100., 50.);
+        assert!(world.subtract(&Vec2f::new(50., 50.)).length() < 1e-4, "{:?}", world);
+    }
+
+    #[test]
+    fn matches_golden_image() {
+        let context = draw_scene();
+        let image = context.get_backend();
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            std::fs::write(GOLDEN_SCENE, image.encode_png().unwrap()).unwrap();
+        }
+        let golden = std::fs::read(GOLDEN_SCENE).unwrap();
+        let mut reader = png::Decoder::new(golden.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((frame.width, frame.height), (image.width(), image.height()));
+        assert!(pixels[..frame.buffer_size()] == *image.pixels(), "rendered scene differs from {}", GOLDEN_SCENE);
     }
 }
@@ -0,0 +1,72 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{Event, HtmlCanvasElement};
+use crate::renderer::backend::ContextStatus;
+use crate::util::log::{log_target, LogLevel};
+
+// Follows the webglcontextlost and webglcontextrestored events of a canvas. The browser drops the GL context of a
+// background tab or after a GPU reset, every GL object made with it is dead from then on and has to be made again
+// once the context comes back. Clones share the listeners, which are removed when the last clone is dropped.
+#[derive(Clone)]
+pub struct ContextLoss {
+    watcher: Rc<Watcher>,
+}
+
+struct Watcher {
+    canvas: HtmlCanvasElement,
+    lost: Rc<Cell<bool>>,
+    restored: Rc<Cell<bool>>,
+    on_lost: Closure<dyn FnMut(Event)>,
+    on_restored: Closure<dyn FnMut(Event)>,
+}
+
+impl ContextLoss {
+    pub fn watch(canvas: &HtmlCanvasElement) -> ContextLoss {
+        let lost = Rc::new(Cell::new(false));
+        let restored = Rc::new(Cell::new(false));
+
+        let lost_flag = lost.clone();
+        let restored_flag = restored.clone();
+        let on_lost = Closure::<dyn FnMut(Event)>::new(move |event: Event| {
+            // without this the browser never gives the context back
+            event.prevent_default();
+            lost_flag.set(true);
+            restored_flag.set(false);
+            log_target(module_path!(), "WebGL context lost, rendering is paused until it is restored", LogLevel::Warn);
+        });
+        let restored_flag = restored.clone();
+        let on_restored = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            restored_flag.set(true);
+        });
+
+        // a listener that cannot be added only means a lost context is not noticed, which is what happened before
+        let _ = canvas.add_event_listener_with_callback("webglcontextlost", on_lost.as_ref().unchecked_ref());
+        let _ = canvas.add_event_listener_with_callback("webglcontextrestored", on_restored.as_ref().unchecked_ref());
+        ContextLoss { watcher: Rc::new(Watcher { canvas: canvas.clone(), lost, restored, on_lost, on_restored }) }
+    }
+
+    pub fn status(&self) -> ContextStatus {
+        if self.watcher.restored.get() {
+            ContextStatus::Restored
+        } else if self.watcher.lost.get() {
+            ContextStatus::Lost
+        } else {
+            ContextStatus::Ready
+        }
+    }
+
+    // the GL objects were made again, drawing can go on
+    pub fn mark_restored(&self) {
+        self.watcher.lost.set(false);
+        self.watcher.restored.set(false);
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let _ = self.canvas.remove_event_listener_with_callback("webglcontextlost", self.on_lost.as_ref().unchecked_ref());
+        let _ = self.canvas.remove_event_listener_with_callback("webglcontextrestored", self.on_restored.as_ref().unchecked_ref());
+    }
+}
@@ -0,0 +1,81 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlCanvasElement, ResizeObserver};
+use crate::util::log::{log_target, LogLevel};
+
+// How many drawing buffer pixels make up one CSS pixel, 1 outside a browser
+#[cfg(target_arch = "wasm32")]
+pub fn device_pixel_ratio() -> f64 {
+    web_sys::window().map_or(1., |window| window.device_pixel_ratio())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn device_pixel_ratio() -> f64 {
+    1.
+}
+
+// CSS pixels to drawing buffer pixels, never less than one so there is always something to draw into
+pub fn to_device_pixels(css_pixels: f64) -> u32 {
+    (css_pixels * device_pixel_ratio()).round().max(1.) as u32
+}
+
+// Follows how large a canvas is shown with a ResizeObserver, so a canvas sized by the page layout can keep its drawing
+// buffer at one pixel per device pixel. devicePixelRatio also changes on browser zoom and when the window moves to
+// another screen without the layout size changing, so it is compared on every check too. Clones share the observer,
+// which is disconnected when the last clone is dropped.
+#[derive(Clone)]
+pub struct DisplaySize {
+    watcher: Rc<Watcher>,
+}
+
+struct Watcher {
+    canvas: HtmlCanvasElement,
+    changed: Rc<Cell<bool>>,
+    pixel_ratio: Cell<f64>,
+    observer: Option<ResizeObserver>,
+    _on_resize: Closure<dyn FnMut(js_sys::Array)>, //called by the observer, so it has to live as long
+}
+
+impl DisplaySize {
+    pub fn watch(canvas: &HtmlCanvasElement) -> DisplaySize {
+        // the first check always reports a change, the canvas may not match its shown size yet
+        let changed = Rc::new(Cell::new(true));
+        let changed_flag = changed.clone();
+        let on_resize = Closure::<dyn FnMut(js_sys::Array)>::new(move |_: js_sys::Array| {
+            changed_flag.set(true);
+        });
+        let observer = match ResizeObserver::new(on_resize.as_ref().unchecked_ref()) {
+            Ok(observer) => {
+                observer.observe(canvas);
+                Some(observer)
+            }
+            Err(_) => {
+                log_target(module_path!(), "ResizeObserver is not available, changes of the canvas layout size are not followed", LogLevel::Warn);
+                None
+            }
+        };
+        DisplaySize { watcher: Rc::new(Watcher { canvas: canvas.clone(), changed, pixel_ratio: Cell::new(device_pixel_ratio()), observer, _on_resize: on_resize }) }
+    }
+
+    // the shown size of the canvas in device pixels
+    pub fn size(&self) -> (u32, u32) {
+        let canvas = &self.watcher.canvas;
+        (to_device_pixels(canvas.client_width() as f64), to_device_pixels(canvas.client_height() as f64))
+    }
+
+    pub fn take_changed(&self) -> bool {
+        let pixel_ratio = device_pixel_ratio();
+        let ratio_changed = self.watcher.pixel_ratio.replace(pixel_ratio) != pixel_ratio;
+        self.watcher.changed.replace(false) || ratio_changed
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(observer) = &self.observer {
+            observer.disconnect();
+        }
+    }
+}
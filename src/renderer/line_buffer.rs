@@ -0,0 +1,35 @@
+use crate::math::Vec2f;
+use crate::renderer::backend::{BufferHandle, Primitive, RenderBackend};
+use crate::util::Error;
+
+// Dynamic list of line segments in world units, re-uploaded every frame.
+pub struct LineBuffer{
+    vertices: Vec<f32>, //x, y, z for each end of each segment
+    vertex_buffer: BufferHandle,
+}
+
+impl LineBuffer{
+    pub fn new(backend: &mut impl RenderBackend) -> Result<LineBuffer, Error> {
+        Ok(LineBuffer{ vertices: Vec::new(), vertex_buffer: backend.create_buffer()? })
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+
+    pub fn push_line(&mut self, from: Vec2f, to: Vec2f) {
+        self.vertices.extend_from_slice(&[from.x, from.y, 0., to.x, to.y, 0.]);
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.vertices.len() / 6
+    }
+
+    pub fn draw(&self, backend: &mut impl RenderBackend) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        backend.upload(self.vertex_buffer, &self.vertices);
+        backend.draw(Primitive::Lines, self.vertex_buffer, self.line_count() * 2);
+    }
+}
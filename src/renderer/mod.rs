@@ -1,5 +1,5 @@
 pub mod shader;
-pub use shader::{create_shader, setup_shaders};
+pub use shader::{create_shader, setup_shaders, create_webgl2_shader, setup_webgl2_shaders};
 
 pub mod context;
 pub use context::Context;
@@ -10,4 +10,31 @@ pub mod vertex_array;
 pub use vertex_array::setup_vertices;
 
 pub mod circle_buffer;
-pub use circle_buffer::CircleBuffer;
\ No newline at end of file
+pub use circle_buffer::CircleBuffer;
+
+pub mod line_buffer;
+pub use line_buffer::LineBuffer;
+
+pub mod backend;
+pub use backend::{BufferHandle, ContextStatus, Primitive, RenderBackend, Uniforms, Viewport};
+
+pub mod webgl_backend;
+pub use webgl_backend::WebGlBackend;
+
+pub mod software_backend;
+pub use software_backend::SoftwareBackend;
+
+pub mod webgl2_backend;
+pub use webgl2_backend::WebGl2Backend;
+
+pub mod canvas_backend;
+pub use canvas_backend::CanvasBackend;
+
+pub mod context_loss;
+pub use context_loss::ContextLoss;
+
+pub mod display_size;
+pub use display_size::DisplaySize;
+
+pub mod camera;
+pub use camera::Camera2D;
\ No newline at end of file
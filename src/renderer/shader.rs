@@ -1,10 +1,10 @@
 
-use web_sys::{WebGlRenderingContext, WebGlShader, WebGlProgram};
-use wasm_bindgen::JsValue;
-use crate::renderer::shader_source::{VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE};
+use web_sys::{WebGlRenderingContext, WebGl2RenderingContext, WebGlShader, WebGlProgram};
+use crate::renderer::shader_source::{VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE, WEBGL2_VERTEX_SHADER_SOURCE, WEBGL2_FRAGMENT_SHADER_SOURCE};
+use crate::util::Error;
 
-pub fn create_shader(gl: &WebGlRenderingContext,shader_type: u32,source: &str,) -> Result<WebGlShader, JsValue> {
-    let shader = gl.create_shader(shader_type).ok_or_else(|| JsValue::from_str("Unable to create shader object"))?;
+pub fn create_shader(gl: &WebGlRenderingContext,shader_type: u32,source: &str,) -> Result<WebGlShader, Error> {
+    let shader = gl.create_shader(shader_type).ok_or_else(|| Error::WebGl("Unable to create shader object".into()))?;
 
     gl.shader_source(&shader, source);
     gl.compile_shader(&shader);
@@ -13,31 +13,29 @@ pub fn create_shader(gl: &WebGlRenderingContext,shader_type: u32,source: &str,)
     {
         Ok(shader)
     } else {
-        Err(JsValue::from_str(
-            &gl.get_shader_info_log(&shader)
+        Err(Error::ShaderCompile(
+            gl.get_shader_info_log(&shader)
                 .unwrap_or_else(|| "Unknown error creating shader".into()),
         ))
     }
 }
 
-pub fn setup_shaders(gl: &WebGlRenderingContext) -> Result<WebGlProgram, JsValue> {
+pub fn setup_shaders(gl: &WebGlRenderingContext) -> Result<WebGlProgram, Error> {
     let vertex_shader_source = VERTEX_SHADER_SOURCE;
     let fragment_shader_source = FRAGMENT_SHADER_SOURCE;
 
     let vertex_shader = create_shader(
-        &gl,
+        gl,
         WebGlRenderingContext::VERTEX_SHADER,
         vertex_shader_source,
-    )
-    .unwrap();
+    )?;
     let fragment_shader = create_shader(
-        &gl,
+        gl,
         WebGlRenderingContext::FRAGMENT_SHADER,
         fragment_shader_source,
-    )
-    .unwrap();
+    )?;
 
-    let shader_program = gl.create_program().unwrap();
+    let shader_program = gl.create_program().ok_or_else(|| Error::WebGl("Unable to create shader program".into()))?;
     gl.attach_shader(&shader_program, &vertex_shader);
     gl.attach_shader(&shader_program, &fragment_shader);
     gl.link_program(&shader_program);
@@ -51,9 +49,46 @@ pub fn setup_shaders(gl: &WebGlRenderingContext) -> Result<WebGlProgram, JsValue
         gl.use_program(Some(&shader_program));
         Ok(shader_program)
     } else {
-        return Err(JsValue::from_str(
-            &gl.get_program_info_log(&shader_program)
+        Err(Error::ShaderLink(
+            gl.get_program_info_log(&shader_program)
                 .unwrap_or_else(|| "Unknown error linking program".into()),
-        ));
+        ))
+    }
+}
+
+pub fn create_webgl2_shader(gl: &WebGl2RenderingContext, shader_type: u32, source: &str) -> Result<WebGlShader, Error> {
+    let shader = gl.create_shader(shader_type).ok_or_else(|| Error::WebGl("Unable to create shader object".into()))?;
+
+    gl.shader_source(&shader, source);
+    gl.compile_shader(&shader);
+
+    if gl.get_shader_parameter(&shader, WebGl2RenderingContext::COMPILE_STATUS).as_bool().unwrap_or(false) {
+        Ok(shader)
+    } else {
+        Err(Error::ShaderCompile(
+            gl.get_shader_info_log(&shader)
+                .unwrap_or_else(|| "Unknown error creating shader".into()),
+        ))
+    }
+}
+
+// the GLSL ES 3.00 shaders, attribute locations are fixed in the source and the tint comes from a uniform block
+pub fn setup_webgl2_shaders(gl: &WebGl2RenderingContext) -> Result<WebGlProgram, Error> {
+    let vertex_shader = create_webgl2_shader(gl, WebGl2RenderingContext::VERTEX_SHADER, WEBGL2_VERTEX_SHADER_SOURCE)?;
+    let fragment_shader = create_webgl2_shader(gl, WebGl2RenderingContext::FRAGMENT_SHADER, WEBGL2_FRAGMENT_SHADER_SOURCE)?;
+
+    let shader_program = gl.create_program().ok_or_else(|| Error::WebGl("Unable to create shader program".into()))?;
+    gl.attach_shader(&shader_program, &vertex_shader);
+    gl.attach_shader(&shader_program, &fragment_shader);
+    gl.link_program(&shader_program);
+
+    if gl.get_program_parameter(&shader_program, WebGl2RenderingContext::LINK_STATUS).as_bool().unwrap_or(false) {
+        gl.use_program(Some(&shader_program));
+        Ok(shader_program)
+    } else {
+        Err(Error::ShaderLink(
+            gl.get_program_info_log(&shader_program)
+                .unwrap_or_else(|| "Unknown error linking program".into()),
+        ))
     }
 }
@@ -2,6 +2,8 @@ precision mediump float;
 
 uniform vec4 fragColor;
 
+varying vec4 v_color;
+
 void main(void) {
-    gl_FragColor = fragColor;
-}
\ No newline at end of file
+    gl_FragColor = fragColor * v_color;
+}
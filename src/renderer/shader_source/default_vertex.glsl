@@ -1,10 +1,17 @@
 attribute vec3 position;
 attribute vec2 offset;
+attribute float scale;
+attribute vec4 color;
+
+uniform mat3 projection;
 
 varying vec2 v_instance_position;
+varying vec4 v_color;
 
 void main(void) {
-    vec2 instance_position = vec2(position.x, position.y) + offset;
+    vec2 instance_position = vec2(position.x, position.y) * scale + offset;
     v_instance_position = instance_position;
-    gl_Position = vec4(instance_position.x, instance_position.y, position.z, 1.0);
-}
\ No newline at end of file
+    v_color = color;
+    vec3 clip_position = projection * vec3(instance_position, 1.0);
+    gl_Position = vec4(clip_position.x, clip_position.y, position.z, 1.0);
+}
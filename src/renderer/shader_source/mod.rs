@@ -1,3 +1,5 @@
 
 pub const VERTEX_SHADER_SOURCE: &'static str = include_str!("default_vertex.glsl");
 pub const FRAGMENT_SHADER_SOURCE: &'static str = include_str!("default_frag.glsl");
+pub const WEBGL2_VERTEX_SHADER_SOURCE: &str = include_str!("webgl2_vertex.glsl");
+pub const WEBGL2_FRAGMENT_SHADER_SOURCE: &str = include_str!("webgl2_frag.glsl");
@@ -0,0 +1,16 @@
+#version 300 es
+precision mediump float;
+
+// must match the block in webgl2_vertex.glsl member for member
+layout(std140) uniform Uniforms {
+    highp vec4 tint;
+    highp mat3 projection;
+};
+
+in vec4 v_color;
+
+out vec4 frag_color;
+
+void main(void) {
+    frag_color = tint * v_color;
+}
@@ -0,0 +1,23 @@
+#version 300 es
+
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 offset;
+layout(location = 2) in float scale;
+layout(location = 3) in vec4 color;
+
+// must match the block in webgl2_frag.glsl member for member
+layout(std140) uniform Uniforms {
+    highp vec4 tint;
+    highp mat3 projection;
+};
+
+out vec2 v_instance_position;
+out vec4 v_color;
+
+void main(void) {
+    vec2 instance_position = vec2(position.x, position.y) * scale + offset;
+    v_instance_position = instance_position;
+    v_color = color;
+    vec3 clip_position = projection * vec3(instance_position, 1.0);
+    gl_Position = vec4(clip_position.x, clip_position.y, position.z, 1.0);
+}
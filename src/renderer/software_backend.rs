@@ -0,0 +1,323 @@
+use std::io::Write;
+use crate::math::{Mat3f, Vec2f, Vec4f};
+use crate::renderer::backend::{BufferHandle, Primitive, RenderBackend, Uniforms, Viewport};
+use crate::renderer::circle_buffer::INSTANCE_FLOATS;
+use crate::util::Error;
+
+const WHITE: Vec4f = Vec4f{ x: 1.0, y: 1.0, z: 1.0, w: 1.0 };
+// in pixels, like the default WebGL line width
+const LINE_WIDTH: f32 = 1.0;
+
+// Rasterizes on the CPU into an RGBA image, for rendering without a browser or GPU. Draws what the WebGL backend
+// draws with the default shader: colors are the instance color times the tint. With antialiasing, edge pixels are
+// blended by how much of them the shape covers, otherwise a pixel is drawn when its center is inside, as on the GPU.
+pub struct SoftwareBackend {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>, //RGBA, 8 bits per channel, rows from the top of the image down
+    buffers: Vec<Vec<f32>>,
+    tint: Vec4f,
+    projection: Mat3f,
+    antialiasing: bool,
+    viewport: Viewport,
+}
+
+// one instance worth of per instance attributes
+struct Instance {
+    offset: Vec2f,
+    scale: f32,
+    color: Vec4f,
+}
+
+fn cross(a: Vec2f, b: Vec2f) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+fn channel(value: f32) -> u8 {
+    (value.clamp(0., 1.) * 255.).round() as u8
+}
+
+impl SoftwareBackend {
+    pub fn new(width: u32, height: u32) -> SoftwareBackend {
+        SoftwareBackend {
+            width,
+            height,
+            pixels: vec![0; width as usize * height as usize * 4],
+            buffers: Vec::new(),
+            tint: WHITE,
+            projection: Mat3f::IDENTITY,
+            antialiasing: true,
+            viewport: Viewport::full(width, height),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        [self.pixels[index], self.pixels[index + 1], self.pixels[index + 2], self.pixels[index + 3]]
+    }
+
+    pub fn set_antialiasing(&mut self, antialiasing: bool) {
+        self.antialiasing = antialiasing;
+    }
+
+    pub fn write_png(&self, writer: impl Write) -> Result<(), Error> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+
+    pub fn encode_png(&self) -> Result<Vec<u8>, Error> {
+        let mut bytes = Vec::new();
+        self.write_png(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // gl coordinates to pixel coordinates inside the viewport, y flipped since rows go from the top down
+    fn to_pixel(&self, gl: Vec2f) -> Vec2f {
+        let viewport = &self.viewport;
+        let x = viewport.x as f32 + (gl.x + 1.0) * 0.5 * viewport.width as f32;
+        let y = viewport.y as f32 + (gl.y + 1.0) * 0.5 * viewport.height as f32;
+        Vec2f::new(x, self.height as f32 - y)
+    }
+
+    // how much of the pixel a shape covers, from the signed distance of the pixel center to its edge, positive outside
+    fn coverage(&self, distance: f32) -> f32 {
+        if self.antialiasing {
+            (0.5 - distance).clamp(0., 1.)
+        } else if distance <= 0. {
+            1.
+        } else {
+            0.
+        }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: [u8; 4], coverage: f32) {
+        if coverage <= 0. || x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let index = (y as usize * self.width as usize + x as usize) * 4;
+        for (destination, source) in self.pixels[index..index + 4].iter_mut().zip(color) {
+            *destination = (*destination as f32 + (source as f32 - *destination as f32) * coverage).round() as u8;
+        }
+    }
+
+    // pixel bounds of the points grown by `margin`, clipped to the viewport like clip space is on the GPU
+    fn pixel_bounds(&self, points: &[Vec2f], margin: f32) -> (i64, i64, i64, i64) {
+        let (min, max) = points.iter().fold((Vec2f::new(f32::MAX, f32::MAX), Vec2f::new(f32::MIN, f32::MIN)), |(min, max), point| {
+            (Vec2f::new(min.x.min(point.x), min.y.min(point.y)), Vec2f::new(max.x.max(point.x), max.y.max(point.y)))
+        });
+        let viewport = &self.viewport;
+        let left = viewport.x.max(0) as f32;
+        let right = ((viewport.x + viewport.width) as f32).min(self.width as f32);
+        let top = (self.height as i32 - viewport.y - viewport.height).max(0) as f32;
+        let bottom = (self.height as i32 - viewport.y) as f32;
+        ((min.x - margin).floor().max(left) as i64, (min.y - margin).floor().max(top) as i64,
+         (max.x + margin).ceil().min(right) as i64, (max.y + margin).ceil().min(bottom.min(self.height as f32)) as i64)
+    }
+
+    // A triangle fan of a convex polygon, filled as one shape so the edges inside it are not blended twice. The signed
+    // distance to the polygon is the largest distance to the lines through its edges, exact inside and close enough
+    // outside for the one pixel wide antialiased rim.
+    fn fill_polygon(&mut self, vertices: &[Vec2f], color: [u8; 4]) {
+        let area: f32 = (0..vertices.len()).map(|i| cross(vertices[i], vertices[(i + 1) % vertices.len()])).sum();
+        if vertices.len() < 3 || area == 0. {
+            return;
+        }
+        // either winding is drawn, face culling is off
+        let sign = area.signum();
+        let edges: Vec<(Vec2f, Vec2f)> = (0..vertices.len())
+            .map(|i| (vertices[i], vertices[(i + 1) % vertices.len()].subtract(&vertices[i])))
+            .filter(|(_, edge)| edge.length() > f32::EPSILON)
+            .map(|(start, edge)| (start, edge.scale(sign / edge.length())))
+            .collect();
+        let (min_x, min_y, max_x, max_y) = self.pixel_bounds(vertices, 1.);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let center = Vec2f::new(x as f32 + 0.5, y as f32 + 0.5);
+                let distance = edges.iter()
+                    .map(|(start, direction)| -cross(*direction, center.subtract(start)))
+                    .fold(f32::MIN, f32::max);
+                self.blend(x, y, color, self.coverage(distance));
+            }
+        }
+    }
+
+    fn draw_line(&mut self, from: Vec2f, to: Vec2f, color: [u8; 4]) {
+        let delta = to.subtract(&from);
+        let length_squared = delta.dot(&delta);
+        let (min_x, min_y, max_x, max_y) = self.pixel_bounds(&[from, to], LINE_WIDTH + 1.);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let center = Vec2f::new(x as f32 + 0.5, y as f32 + 0.5);
+                let t = if length_squared > 0. { (center.subtract(&from).dot(&delta) / length_squared).clamp(0., 1.) } else { 0. };
+                let distance = center.subtract(&from.add(&delta.scale(t))).length();
+                self.blend(x, y, color, self.coverage(distance - LINE_WIDTH * 0.5));
+            }
+        }
+    }
+
+    fn rasterize(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instance: &Instance) {
+        let color = self.shade(instance.color);
+        let vertices: Vec<Vec2f> = self.buffers[mesh.0]
+            .chunks_exact(3)
+            .take(vertex_count)
+            .map(|vertex| {
+                let world = Vec2f::new(vertex[0], vertex[1]).scale(instance.scale).add(&instance.offset);
+                self.to_pixel(self.projection.transform_point(world))
+            })
+            .collect();
+        match primitive {
+            Primitive::TriangleFan => self.fill_polygon(&vertices, color),
+            Primitive::Lines => {
+                for segment in vertices.chunks_exact(2) {
+                    self.draw_line(segment[0], segment[1], color);
+                }
+            }
+        }
+    }
+
+    // the default fragment shader: tint times the vertex color
+    fn shade(&self, color: Vec4f) -> [u8; 4] {
+        [channel(color.x * self.tint.x), channel(color.y * self.tint.y), channel(color.z * self.tint.z), channel(color.w * self.tint.w)]
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn create_buffer(&mut self) -> Result<BufferHandle, Error> {
+        self.buffers.push(Vec::new());
+        Ok(BufferHandle(self.buffers.len() - 1))
+    }
+
+    fn upload(&mut self, buffer: BufferHandle, data: &[f32]) {
+        let contents = &mut self.buffers[buffer.0];
+        contents.clear();
+        contents.extend_from_slice(data);
+    }
+
+    fn set_uniforms(&mut self, uniforms: &Uniforms) {
+        self.tint = uniforms.tint;
+        self.projection = uniforms.projection;
+    }
+
+    fn clear(&mut self, color: Vec4f) {
+        let color = [channel(color.x), channel(color.y), channel(color.z), channel(color.w)];
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.pixels = vec![0; width as usize * height as usize * 4];
+        self.viewport = Viewport::full(width, height);
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = viewport;
+    }
+
+    fn draw(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize) {
+        let instance = Instance { offset: Vec2f::new(0., 0.), scale: 1., color: WHITE };
+        self.rasterize(primitive, mesh, vertex_count, &instance);
+    }
+
+    fn draw_instanced(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instances: BufferHandle, instance_count: usize) {
+        let instances: Vec<Instance> = self.buffers[instances.0]
+            .chunks_exact(INSTANCE_FLOATS)
+            .take(instance_count)
+            .map(|data| Instance {
+                offset: Vec2f::new(data[0], data[1]),
+                scale: data[2],
+                color: Vec4f::new(data[3], data[4], data[5], data[6]),
+            })
+            .collect();
+        for instance in &instances {
+            self.rasterize(primitive, mesh, vertex_count, instance);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: u32 = 32;
+
+    // a 32 sided polygon of `radius` in clip space, the same fan CircleBuffer draws
+    fn circle_mesh(backend: &mut SoftwareBackend, radius: f32) -> (BufferHandle, usize) {
+        let vertices: Vec<f32> = (0..32)
+            .flat_map(|i| {
+                let angle = 2.0 * std::f32::consts::PI * i as f32 / 32.;
+                [radius * angle.cos(), radius * angle.sin(), 0.]
+            })
+            .collect();
+        let mesh = backend.create_buffer().unwrap();
+        backend.upload(mesh, &vertices);
+        (mesh, 32)
+    }
+
+    // draws a white circle on a transparent image, so alpha is the coverage of each pixel
+    fn draw_circle(antialiasing: bool) -> SoftwareBackend {
+        let mut backend = SoftwareBackend::new(SIZE, SIZE);
+        backend.set_antialiasing(antialiasing);
+        backend.clear(Vec4f::new(0., 0., 0., 0.));
+        let (mesh, vertex_count) = circle_mesh(&mut backend, 0.53);
+        backend.draw(Primitive::TriangleFan, mesh, vertex_count);
+        backend
+    }
+
+    // alpha of the outermost pixel the circle touches on the row through its center
+    fn edge_alpha(backend: &SoftwareBackend) -> u8 {
+        let row = SIZE / 2;
+        let edge = (0..SIZE).rev().find(|x| backend.pixel(*x, row)[3] > 0).unwrap();
+        backend.pixel(edge, row)[3]
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let backend = draw_circle(true);
+        let bytes = backend.encode_png().unwrap();
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((frame.width, frame.height), (SIZE, SIZE));
+        assert_eq!(frame.color_type, png::ColorType::Rgba);
+        assert_eq!(frame.bit_depth, png::BitDepth::Eight);
+        assert!(pixels[..frame.buffer_size()] == *backend.pixels());
+    }
+
+    #[test]
+    fn antialiased_edges_are_partially_covered() {
+        let backend = draw_circle(true);
+        let alpha = edge_alpha(&backend);
+        assert!(alpha > 0 && alpha < 255, "edge alpha {}", alpha);
+        assert_eq!(backend.pixel(SIZE / 2, SIZE / 2)[3], 255);
+    }
+
+    #[test]
+    fn aliased_edges_are_fully_covered() {
+        let backend = draw_circle(false);
+        assert_eq!(edge_alpha(&backend), 255);
+        assert!(backend.pixels().chunks_exact(4).all(|pixel| pixel[3] == 0 || pixel[3] == 255));
+    }
+}
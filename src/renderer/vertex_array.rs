@@ -1,13 +1,14 @@
 use web_sys::{WebGlRenderingContext, WebGlProgram, WebGlBuffer};
+use crate::util::Error;
 
 struct VertexBuffer{
     handle: WebGlBuffer
 }
 
 impl VertexBuffer{
-    fn new(gl: &WebGlRenderingContext, vertices: &[f32], shader_program: &WebGlProgram) -> VertexBuffer {
+    fn new(gl: &WebGlRenderingContext, vertices: &[f32], shader_program: &WebGlProgram) -> Result<VertexBuffer, Error> {
         // Create the OpenGL bufer and get a handle
-        let id: WebGlBuffer = gl.create_buffer().unwrap();
+        let id: WebGlBuffer = gl.create_buffer().ok_or_else(|| Error::WebGl("Unable to create buffer".into()))?;
 
         // Bind buffer
         gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&id));
@@ -31,7 +32,7 @@ impl VertexBuffer{
             0,
         );
         gl.enable_vertex_attrib_array(coordinates_location as u32);
-        return VertexBuffer { handle: id }
+        Ok(VertexBuffer { handle: id })
     }
 
     #[allow(dead_code)]
@@ -50,6 +51,7 @@ impl VertexBuffer{
     }
 }
 
-pub fn setup_vertices(gl: &WebGlRenderingContext, vertices: &[f32], shader_program: &WebGlProgram) {
-    let _vertex_buffer = VertexBuffer::new(gl, vertices, shader_program);
+pub fn setup_vertices(gl: &WebGlRenderingContext, vertices: &[f32], shader_program: &WebGlProgram) -> Result<(), Error> {
+    let _vertex_buffer = VertexBuffer::new(gl, vertices, shader_program)?;
+    Ok(())
 }
\ No newline at end of file
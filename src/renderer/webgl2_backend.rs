@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGl2RenderingContext, WebGlBuffer, WebGlVertexArrayObject, HtmlCanvasElement};
+use crate::math::Vec4f;
+use crate::renderer::backend::{BufferHandle, ContextStatus, Primitive, RenderBackend, Uniforms, Viewport};
+use crate::renderer::{ContextLoss, DisplaySize};
+use crate::renderer::circle_buffer::{INSTANCE_STRIDE, SCALE_OFFSET, COLOR_OFFSET};
+use crate::renderer::setup_webgl2_shaders;
+use crate::util::Error;
+
+// attribute locations, fixed by the layout qualifiers in webgl2_vertex.glsl
+const POSITION_ATTRIB: u32 = 0;
+const OFFSET_ATTRIB: u32 = 1;
+const SCALE_ATTRIB: u32 = 2;
+const COLOR_ATTRIB: u32 = 3;
+// binding point of the Uniforms block
+const UNIFORMS_BINDING: u32 = 0;
+// floats in the Uniforms block with std140 layout, the tint and three padded matrix columns
+const UNIFORMS_FLOATS: usize = 16;
+
+// WebGL2 with native instancing. Every mesh and instance buffer pair gets a vertex array object the first time it is
+// drawn, so a draw only binds the VAO instead of pointing every attribute again. Uniforms live in a uniform buffer.
+pub struct WebGl2Backend {
+    canvas: HtmlCanvasElement,
+    context: WebGl2RenderingContext,
+    buffers: Vec<WebGlBuffer>,
+    vertex_arrays: HashMap<(BufferHandle, Option<BufferHandle>), WebGlVertexArrayObject>,
+    uniform_buffer: WebGlBuffer,
+    context_loss: ContextLoss,
+    display_size: DisplaySize,
+}
+
+impl WebGl2Backend {
+    pub fn new(canvas: HtmlCanvasElement, context: WebGl2RenderingContext) -> Result<WebGl2Backend, Error> {
+        let context_loss = ContextLoss::watch(&canvas);
+        let display_size = DisplaySize::watch(&canvas);
+        Self::with_context(canvas, context, context_loss, display_size)
+    }
+
+    // everything made with the context, so a restored context can be set up the same way
+    fn with_context(canvas: HtmlCanvasElement, context: WebGl2RenderingContext, context_loss: ContextLoss, display_size: DisplaySize) -> Result<WebGl2Backend, Error> {
+        let active_shader = setup_webgl2_shaders(&context)?;
+
+        let uniform_buffer = context.create_buffer().ok_or_else(|| Error::WebGl("Unable to create uniform buffer".into()))?;
+        context.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&uniform_buffer));
+        context.buffer_data_with_i32(WebGl2RenderingContext::UNIFORM_BUFFER, (UNIFORMS_FLOATS * std::mem::size_of::<f32>()) as i32, WebGl2RenderingContext::DYNAMIC_DRAW);
+        let block_index = context.get_uniform_block_index(&active_shader, "Uniforms");
+        context.uniform_block_binding(&active_shader, block_index, UNIFORMS_BINDING);
+        context.bind_buffer_base(WebGl2RenderingContext::UNIFORM_BUFFER, UNIFORMS_BINDING, Some(&uniform_buffer));
+
+        context.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+        let mut backend = WebGl2Backend{
+            canvas,
+            context,
+            buffers: Vec::new(),
+            vertex_arrays: HashMap::new(),
+            uniform_buffer,
+            context_loss,
+            display_size,
+        };
+        // the uniform buffer starts out zero, a zero projection would squash everything into a point
+        backend.set_uniforms(&Uniforms::default());
+        Ok(backend)
+    }
+
+    // None when the browser does not support WebGL2
+    pub fn get_rendering_context(canvas: &HtmlCanvasElement) -> Option<WebGl2RenderingContext> {
+        canvas.get_context("webgl2").ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<WebGl2RenderingContext>().ok())
+    }
+
+    pub fn get_gl_context(&self) -> &WebGl2RenderingContext {
+        &self.context
+    }
+
+    fn mode(primitive: Primitive) -> u32 {
+        match primitive {
+            Primitive::TriangleFan => WebGl2RenderingContext::TRIANGLE_FAN,
+            Primitive::Lines => WebGl2RenderingContext::LINES,
+        }
+    }
+
+    // Binds the vertex array for the mesh and optional instance buffer, recording it on first use. False when the
+    // vertex array could not be created, the draw is skipped then.
+    fn bind_vertex_array(&mut self, mesh: BufferHandle, instances: Option<BufferHandle>) -> bool {
+        if let Some(vertex_array) = self.vertex_arrays.get(&(mesh, instances)) {
+            self.context.bind_vertex_array(Some(vertex_array));
+            return true;
+        }
+        let gl = &self.context;
+        let Some(vertex_array) = gl.create_vertex_array() else { return false };
+        gl.bind_vertex_array(Some(&vertex_array));
+
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffers[mesh.0]));
+        gl.vertex_attrib_pointer_with_i32(POSITION_ATTRIB, 3, WebGl2RenderingContext::FLOAT, false, 0, 0);
+        gl.enable_vertex_attrib_array(POSITION_ATTRIB);
+
+        // without instances the per instance attributes stay disabled and read the constants set in draw
+        if let Some(instances) = instances {
+            gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffers[instances.0]));
+            for (attrib, size, offset) in [(OFFSET_ATTRIB, 2, 0), (SCALE_ATTRIB, 1, SCALE_OFFSET), (COLOR_ATTRIB, 4, COLOR_OFFSET)] {
+                gl.vertex_attrib_pointer_with_i32(attrib, size, WebGl2RenderingContext::FLOAT, false, INSTANCE_STRIDE, offset);
+                gl.enable_vertex_attrib_array(attrib);
+                gl.vertex_attrib_divisor(attrib, 1);
+            }
+        }
+        self.vertex_arrays.insert((mesh, instances), vertex_array);
+        true
+    }
+}
+
+impl RenderBackend for WebGl2Backend {
+    fn create_buffer(&mut self) -> Result<BufferHandle, Error> {
+        let buffer = self.context.create_buffer().ok_or_else(|| Error::WebGl("Unable to create buffer".into()))?;
+        self.buffers.push(buffer);
+        Ok(BufferHandle(self.buffers.len() - 1))
+    }
+
+    fn upload(&mut self, buffer: BufferHandle, data: &[f32]) {
+        let gl = &self.context;
+        gl.bind_buffer(WebGl2RenderingContext::ARRAY_BUFFER, Some(&self.buffers[buffer.0]));
+        // the view must not outlive `data`, it is copied into the buffer straight away
+        let array = unsafe { js_sys::Float32Array::view(data) };
+        gl.buffer_data_with_array_buffer_view(
+            WebGl2RenderingContext::ARRAY_BUFFER,
+            &array,
+            WebGl2RenderingContext::DYNAMIC_DRAW,
+        );
+    }
+
+    fn set_uniforms(&mut self, uniforms: &Uniforms) {
+        let gl = &self.context;
+        gl.bind_buffer(WebGl2RenderingContext::UNIFORM_BUFFER, Some(&self.uniform_buffer));
+        let mut data = [0.; UNIFORMS_FLOATS];
+        data[..4].copy_from_slice(&uniforms.tint.as_float_array());
+        data[4..].copy_from_slice(&uniforms.projection.as_std140_array());
+        let array = unsafe { js_sys::Float32Array::view(&data) };
+        gl.buffer_sub_data_with_i32_and_array_buffer_view(WebGl2RenderingContext::UNIFORM_BUFFER, 0, &array);
+    }
+
+    fn clear(&mut self, color: Vec4f) {
+        self.context.clear_color(color.x, color.y, color.z, color.w);
+        self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.canvas.width(), self.canvas.height())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.canvas.set_width(width);
+        self.canvas.set_height(height);
+        self.set_viewport(Viewport::full(width, height));
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.context.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+    }
+
+    fn display_size(&self) -> Option<(u32, u32)> {
+        Some(self.display_size.size())
+    }
+
+    fn display_size_changed(&mut self) -> bool {
+        self.display_size.take_changed()
+    }
+
+    fn context_status(&self) -> ContextStatus {
+        self.context_loss.status()
+    }
+
+    // the vertex arrays go with the old context, they are recorded again on the next draws
+    fn restore(&mut self) -> Result<(), Error> {
+        let mut restored = Self::with_context(self.canvas.clone(), self.context.clone(), self.context_loss.clone(), self.display_size.clone())?;
+        for _ in 0..self.buffers.len() {
+            restored.create_buffer()?;
+        }
+        *self = restored;
+        self.context_loss.mark_restored();
+        Ok(())
+    }
+
+    fn draw(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize) {
+        if !self.bind_vertex_array(mesh, None) {
+            return;
+        }
+        let gl = &self.context;
+        gl.vertex_attrib2f(OFFSET_ATTRIB, 0., 0.);
+        gl.vertex_attrib1f(SCALE_ATTRIB, 1.);
+        gl.vertex_attrib4f(COLOR_ATTRIB, 1., 1., 1., 1.);
+        gl.draw_arrays(Self::mode(primitive), 0, vertex_count as i32);
+        gl.bind_vertex_array(None);
+    }
+
+    fn draw_instanced(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instances: BufferHandle, instance_count: usize) {
+        if !self.bind_vertex_array(mesh, Some(instances)) {
+            return;
+        }
+        let gl = &self.context;
+        gl.draw_arrays_instanced(Self::mode(primitive), 0, vertex_count as i32, instance_count as i32);
+        gl.bind_vertex_array(None);
+    }
+}
@@ -0,0 +1,219 @@
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGlRenderingContext, WebGl2RenderingContext, WebGlProgram, WebGlBuffer, WebGlUniformLocation, HtmlCanvasElement};
+use crate::math::Vec4f;
+use crate::renderer::backend::{BufferHandle, ContextStatus, Primitive, RenderBackend, Uniforms, Viewport};
+use crate::renderer::{ContextLoss, DisplaySize};
+use crate::renderer::circle_buffer::{INSTANCE_STRIDE, SCALE_OFFSET, COLOR_OFFSET};
+use crate::renderer::setup_shaders;
+use crate::util::log::{log_throttled, LogLevel};
+use crate::util::Error;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = ANGLEInstancedArrays)]
+    pub type AngleInstancedArrays;
+
+    #[wasm_bindgen(method, catch, js_name = drawArraysInstancedANGLE)]
+    pub fn draw_arrays_instanced_angle(this: &AngleInstancedArrays, mode: u32, first: i32, count: i32, primcount: i32) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method, js_name = vertexAttribDivisorANGLE)]
+    pub fn vertex_attrib_divisor_angle(this: &AngleInstancedArrays, index: u32, divisor: u32);
+}
+
+// WebGL1 with ANGLE_instanced_arrays, drawing with the default shader into a canvas
+pub struct WebGlBackend {
+    canvas: HtmlCanvasElement,
+    context: WebGlRenderingContext,
+    active_shader: WebGlProgram,
+    ext: AngleInstancedArrays,
+    buffers: Vec<WebGlBuffer>,
+    position_attrib: u32,
+    offset_attrib: u32,
+    scale_attrib: u32,
+    color_attrib: u32,
+    color_location: Option<WebGlUniformLocation>,
+    projection_location: Option<WebGlUniformLocation>,
+    context_loss: ContextLoss,
+    display_size: DisplaySize,
+}
+
+impl WebGlBackend {
+    pub fn new(canvas: HtmlCanvasElement) -> Result<WebGlBackend, Error> {
+        let context = Self::get_rendering_context(&canvas)?;
+        let context_loss = ContextLoss::watch(&canvas);
+        let display_size = DisplaySize::watch(&canvas);
+        Self::with_context(canvas, context, context_loss, display_size)
+    }
+
+    // everything made with the context, so a restored context can be set up the same way
+    fn with_context(canvas: HtmlCanvasElement, context: WebGlRenderingContext, context_loss: ContextLoss, display_size: DisplaySize) -> Result<WebGlBackend, Error> {
+        let active_shader = setup_shaders(&context)?;
+        let ext = Self::get_angle_extension(&context)?;
+        let position_attrib = context.get_attrib_location(&active_shader, "position") as u32;
+        let offset_attrib = context.get_attrib_location(&active_shader, "offset") as u32;
+        let scale_attrib = context.get_attrib_location(&active_shader, "scale") as u32;
+        let color_attrib = context.get_attrib_location(&active_shader, "color") as u32;
+        let color_location = context.get_uniform_location(&active_shader, "fragColor");
+        let projection_location = context.get_uniform_location(&active_shader, "projection");
+        context.viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+
+        let mut backend = WebGlBackend{
+            canvas,
+            context,
+            active_shader,
+            ext,
+            buffers: Vec::new(),
+            position_attrib,
+            offset_attrib,
+            scale_attrib,
+            color_attrib,
+            color_location,
+            projection_location,
+            context_loss,
+            display_size,
+        };
+        // uniforms start out zero, a zero projection would squash everything into a point
+        backend.set_uniforms(&Uniforms::default());
+        Ok(backend)
+    }
+
+    pub fn get_gl_context(&self) -> &WebGlRenderingContext {
+        &self.context
+    }
+
+    pub fn get_active_shader(&self) -> &WebGlProgram {
+        &self.active_shader
+    }
+
+    fn get_rendering_context(canvas: &HtmlCanvasElement) -> Result<WebGlRenderingContext, Error> {
+        canvas.get_context("webgl").ok()
+            .flatten()
+            .and_then(|context| context.dyn_into::<WebGlRenderingContext>().ok())
+            .ok_or_else(|| Error::WebGl("this browser does not support WebGL".into()))
+    }
+
+    fn get_angle_extension(gl: &WebGlRenderingContext) -> Result<AngleInstancedArrays, Error> {
+        let extension: js_sys::Object = gl.get_extension("ANGLE_instanced_arrays").ok()
+            .flatten()
+            .ok_or_else(|| Error::MissingExtension("ANGLE_instanced_arrays".into()))?;
+        Ok(extension.unchecked_into::<AngleInstancedArrays>())
+    }
+
+    fn mode(primitive: Primitive) -> u32 {
+        match primitive {
+            Primitive::TriangleFan => WebGlRenderingContext::TRIANGLE_FAN,
+            Primitive::Lines => WebGlRenderingContext::LINES,
+        }
+    }
+
+    fn bind_mesh(&self, mesh: BufferHandle) {
+        let gl = &self.context;
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buffers[mesh.0]));
+        gl.vertex_attrib_pointer_with_i32(self.position_attrib, 3, WebGlRenderingContext::FLOAT, false, 0, 0);
+        gl.enable_vertex_attrib_array(self.position_attrib);
+    }
+}
+
+impl RenderBackend for WebGlBackend {
+    fn create_buffer(&mut self) -> Result<BufferHandle, Error> {
+        let buffer = self.context.create_buffer().ok_or_else(|| Error::WebGl("Unable to create buffer".into()))?;
+        self.buffers.push(buffer);
+        Ok(BufferHandle(self.buffers.len() - 1))
+    }
+
+    fn upload(&mut self, buffer: BufferHandle, data: &[f32]) {
+        let gl = &self.context;
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buffers[buffer.0]));
+        // the view must not outlive `data`, it is copied into the buffer straight away
+        let array = unsafe { js_sys::Float32Array::view(data) };
+        gl.buffer_data_with_array_buffer_view(
+            WebGlRenderingContext::ARRAY_BUFFER,
+            &array,
+            WebGlRenderingContext::DYNAMIC_DRAW,
+        );
+    }
+
+    fn set_uniforms(&mut self, uniforms: &Uniforms) {
+        self.context.uniform4fv_with_f32_array(self.color_location.as_ref(), &uniforms.tint.as_float_array());
+        self.context.uniform_matrix3fv_with_f32_array(self.projection_location.as_ref(), false, &uniforms.projection.as_float_array());
+    }
+
+    fn clear(&mut self, color: Vec4f) {
+        self.context.clear_color(color.x, color.y, color.z, color.w);
+        self.context.clear(WebGl2RenderingContext::COLOR_BUFFER_BIT);
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.canvas.width(), self.canvas.height())
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.canvas.set_width(width);
+        self.canvas.set_height(height);
+        self.set_viewport(Viewport::full(width, height));
+    }
+
+    fn set_viewport(&mut self, viewport: Viewport) {
+        self.context.viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+    }
+
+    fn display_size(&self) -> Option<(u32, u32)> {
+        Some(self.display_size.size())
+    }
+
+    fn display_size_changed(&mut self) -> bool {
+        self.display_size.take_changed()
+    }
+
+    fn context_status(&self) -> ContextStatus {
+        self.context_loss.status()
+    }
+
+    fn restore(&mut self) -> Result<(), Error> {
+        let mut restored = Self::with_context(self.canvas.clone(), self.context.clone(), self.context_loss.clone(), self.display_size.clone())?;
+        for _ in 0..self.buffers.len() {
+            restored.create_buffer()?;
+        }
+        *self = restored;
+        self.context_loss.mark_restored();
+        Ok(())
+    }
+
+    fn draw(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize) {
+        let gl = &self.context;
+        self.bind_mesh(mesh);
+
+        // not instanced, so the per instance attributes fall back to constants
+        gl.disable_vertex_attrib_array(self.offset_attrib);
+        gl.vertex_attrib2f(self.offset_attrib, 0., 0.);
+        gl.disable_vertex_attrib_array(self.scale_attrib);
+        gl.vertex_attrib1f(self.scale_attrib, 1.);
+        gl.disable_vertex_attrib_array(self.color_attrib);
+        gl.vertex_attrib4f(self.color_attrib, 1., 1., 1., 1.);
+
+        gl.draw_arrays(Self::mode(primitive), 0, vertex_count as i32);
+    }
+
+    fn draw_instanced(&mut self, primitive: Primitive, mesh: BufferHandle, vertex_count: usize, instances: BufferHandle, instance_count: usize) {
+        let gl = &self.context;
+        self.bind_mesh(mesh);
+
+        // per instance attributes are interleaved in the instance buffer
+        gl.bind_buffer(WebGlRenderingContext::ARRAY_BUFFER, Some(&self.buffers[instances.0]));
+        for (attrib, size, offset) in [(self.offset_attrib, 2, 0), (self.scale_attrib, 1, SCALE_OFFSET), (self.color_attrib, 4, COLOR_OFFSET)] {
+            gl.vertex_attrib_pointer_with_i32(attrib, size, WebGlRenderingContext::FLOAT, false, INSTANCE_STRIDE, offset);
+            gl.enable_vertex_attrib_array(attrib);
+            self.ext.vertex_attrib_divisor_angle(attrib, 1);
+        }
+        // a failed draw loses one frame, the next one tries again
+        if let Err(error) = self.ext.draw_arrays_instanced_angle(Self::mode(primitive), 0, vertex_count as i32, instance_count as i32) {
+            log_throttled(module_path!(), &format!("Failed to draw instances: {}", Error::from(error)), LogLevel::Error, 1000.);
+        }
+
+        // the divisor is per attribute, so clear it before any non instanced draw
+        for attrib in [self.offset_attrib, self.scale_attrib, self.color_attrib] {
+            self.ext.vertex_attrib_divisor_angle(attrib, 0);
+        }
+    }
+}
@@ -1,24 +1,114 @@
+use std::fmt;
+use wasm_bindgen::{JsCast, JsValue};
 
+#[derive(Debug)]
 pub enum Error {
-    IoError(std::io::Error),
-    ParseError(std::num::ParseIntError),
-    CustomError(String)
+    Io(std::io::Error),
+    Parse(std::num::ParseIntError),
+    Custom(String),
+    // no canvas with the requested id, or no document to look it up in
+    Canvas(String),
+    // the browser has no WebGL, or refused to create a context or a gl object
+    WebGl(String),
+    // name of a WebGL extension the renderer needs and the browser does not have
+    MissingExtension(String),
+    // info log of a shader that failed to compile
+    ShaderCompile(String),
+    // info log of a shader program that failed to link
+    ShaderLink(String),
+    // a setting outside the range the simulation accepts
+    Config(String),
+    // data that could not be encoded or decoded, like an exported image
+    Serialization(String),
+    // exception thrown by a JS call
+    Js(String),
+}
+
+impl Error {
+    // The name the error gets on the JS side, so integration code can tell the kinds apart without parsing
+    // messages. Everything that means the browser cannot render is a WebGlError.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Error::Io(_) => "IoError",
+            Error::Parse(_) => "ParseError",
+            Error::Custom(_) | Error::Js(_) => "Error",
+            Error::Canvas(_) => "CanvasError",
+            Error::WebGl(_) | Error::MissingExtension(_) => "WebGlError",
+            Error::ShaderCompile(_) | Error::ShaderLink(_) => "ShaderError",
+            Error::Config(_) => "ConfigError",
+            Error::Serialization(_) => "SerializationError",
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
-        Error::IoError(error)
+        Error::Io(error)
     }
 }
 
 impl From<std::num::ParseIntError> for Error {
     fn from(error: std::num::ParseIntError) -> Self {
-        Error::ParseError(error)
+        Error::Parse(error)
     }
 }
 
 impl From<String> for Error {
     fn from(error: String) -> Self {
-        Error::CustomError(error)
+        Error::Custom(error)
+    }
+}
+
+impl From<png::EncodingError> for Error {
+    fn from(error: png::EncodingError) -> Self {
+        Error::Serialization(error.to_string())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Parse(error) => write!(f, "{}", error),
+            Error::Custom(message) => write!(f, "{}", message),
+            Error::Canvas(message) => write!(f, "Canvas error: {}", message),
+            Error::WebGl(message) => write!(f, "WebGL error: {}", message),
+            Error::MissingExtension(name) => write!(f, "Missing WebGL extension: {}", name),
+            Error::ShaderCompile(log) => write!(f, "Shader failed to compile: {}", log),
+            Error::ShaderLink(log) => write!(f, "Shader program failed to link: {}", log),
+            Error::Config(message) => write!(f, "Invalid configuration: {}", message),
+            Error::Serialization(message) => write!(f, "Serialization error: {}", message),
+            Error::Js(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            Error::Parse(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// thrown on the JS side as an Error with the message and the name of the kind
+impl From<Error> for JsValue {
+    fn from(error: Error) -> Self {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name(error.name());
+        js_error.into()
+    }
+}
+
+// whatever a JS call threw, an Error keeps its message and anything else is shown as it is
+impl From<JsValue> for Error {
+    fn from(value: JsValue) -> Self {
+        let message = match value.dyn_ref::<js_sys::Error>() {
+            Some(js_error) => String::from(js_error.message()),
+            None => value.as_string().unwrap_or_else(|| format!("{:?}", value)),
+        };
+        Error::Js(message)
     }
 }
\ No newline at end of file
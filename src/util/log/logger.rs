@@ -1,12 +1,217 @@
 extern crate web_sys;
 
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use wasm_bindgen::prelude::*;
+use crate::util::profiler::now_ms;
+
+pub const DEFAULT_TARGET: &str = "crab_gl";
+pub const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Info;
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum LogLevel {
-    Warning
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    // only as a filter, lets nothing through
+    Off,
+}
+
+// Minimum level a message needs to be written, for everything and for targets. A target filter also covers the
+// targets nested under it, "crab_gl::game" covers "crab_gl::game::world", and the longest matching one wins.
+struct Filter {
+    level: LogLevel,
+    targets: HashMap<String, LogLevel>,
+}
+
+impl Filter {
+    fn level_for(&self, target: &str) -> LogLevel {
+        self.targets.iter()
+            .filter(|(prefix, _)| target == prefix.as_str() || target.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with("::")))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.level, |(_, level)| *level)
+    }
+}
+
+// when each throttled message last went out and how many were dropped since
+struct Throttle {
+    last_ms: f64,
+    suppressed: u32,
+}
+
+impl Throttle {
+    fn new() -> Self {
+        Throttle { last_ms: f64::NEG_INFINITY, suppressed: 0 }
+    }
+
+    // Some with the number of messages dropped since the last one when a message may go out at `now_ms`
+    fn admit(&mut self, now_ms: f64, interval_ms: f64) -> Option<u32> {
+        if now_ms - self.last_ms < interval_ms {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_ms = now_ms;
+        Some(std::mem::take(&mut self.suppressed))
+    }
+}
+
+fn filter() -> &'static Mutex<Filter> {
+    static FILTER: OnceLock<Mutex<Filter>> = OnceLock::new();
+    FILTER.get_or_init(|| Mutex::new(Filter { level: DEFAULT_LOG_LEVEL, targets: HashMap::new() }))
+}
+
+fn throttles() -> &'static Mutex<HashMap<(String, LogLevel), Throttle>> {
+    static THROTTLES: OnceLock<Mutex<HashMap<(String, LogLevel), Throttle>>> = OnceLock::new();
+    THROTTLES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub fn set_level(level: LogLevel) {
+    if let Ok(mut filter) = filter().lock() {
+        filter.level = level;
+    }
+}
+
+pub fn set_target_level(target: &str, level: LogLevel) {
+    if let Ok(mut filter) = filter().lock() {
+        filter.targets.insert(target.to_string(), level);
+    }
+}
+
+pub fn clear_target_levels() {
+    if let Ok(mut filter) = filter().lock() {
+        filter.targets.clear();
+    }
+}
+
+pub fn enabled(target: &str, level: LogLevel) -> bool {
+    level != LogLevel::Off && filter().lock().is_ok_and(|filter| level >= filter.level_for(target))
 }
 
 pub fn log(msg: &str, level: LogLevel) {
+    log_target(DEFAULT_TARGET, msg, level);
+}
+
+pub fn log_target(target: &str, msg: &str, level: LogLevel) {
+    if enabled(target, level) {
+        write(target, msg, level);
+    }
+}
+
+// For messages that would otherwise go out every frame: at most one per `interval_ms` for each target and level,
+// the next one that goes out says how many were dropped in between.
+pub fn log_throttled(target: &str, msg: &str, level: LogLevel, interval_ms: f64) {
+    if !enabled(target, level) {
+        return;
+    }
+    let now = now_ms();
+    let admitted = {
+        let Ok(mut throttles) = throttles().lock() else { return };
+        throttles.entry((target.to_string(), level)).or_insert_with(Throttle::new).admit(now, interval_ms)
+    };
+    let Some(suppressed) = admitted else { return };
+    if suppressed > 0 {
+        write(target, &format!("{} ({} similar messages suppressed)", msg, suppressed), level);
+    } else {
+        write(target, msg, level);
+    }
+}
+
+// the browser console method that matches the level, console.trace would add a stack trace to every message
+#[cfg(target_arch = "wasm32")]
+fn write(target: &str, msg: &str, level: LogLevel) {
+    let line = format!("[{}] {}", target, msg).into();
     match level {
-        LogLevel::Warning => web_sys::console::log_1(&msg.into())
+        LogLevel::Trace | LogLevel::Debug => web_sys::console::debug_1(&line),
+        LogLevel::Info => web_sys::console::info_1(&line),
+        LogLevel::Warn => web_sys::console::warn_1(&line),
+        LogLevel::Error => web_sys::console::error_1(&line),
+        LogLevel::Off => {}
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write(target: &str, msg: &str, level: LogLevel) {
+    if level != LogLevel::Off {
+        eprintln!("{:?} [{}] {}", level, target, msg);
+    }
+}
+
+// Routes the `log` crate macros, ours and those of dependencies, through the same filter and sinks.
+struct Logger;
+
+impl From<::log::Level> for LogLevel {
+    fn from(level: ::log::Level) -> Self {
+        match level {
+            ::log::Level::Trace => LogLevel::Trace,
+            ::log::Level::Debug => LogLevel::Debug,
+            ::log::Level::Info => LogLevel::Info,
+            ::log::Level::Warn => LogLevel::Warn,
+            ::log::Level::Error => LogLevel::Error,
+        }
+    }
+}
+
+impl ::log::Log for Logger {
+    fn enabled(&self, metadata: &::log::Metadata) -> bool {
+        enabled(metadata.target(), metadata.level().into())
+    }
+
+    fn log(&self, record: &::log::Record) {
+        log_target(record.target(), &record.args().to_string(), record.level().into());
     }
-    println!("{}", msg);
-}
\ No newline at end of file
+
+    fn flush(&self) {}
+}
+
+static LOGGER: Logger = Logger;
+
+// Installs the logger behind the `log` crate macros. Filtering happens here, so the facade lets everything through.
+// Does nothing if another logger was installed first.
+pub fn init() {
+    if ::log::set_logger(&LOGGER).is_ok() {
+        ::log::set_max_level(::log::LevelFilter::Trace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_with(targets: &[(&str, LogLevel)]) -> Filter {
+        Filter { level: LogLevel::Warn, targets: targets.iter().map(|(target, level)| (target.to_string(), *level)).collect() }
+    }
+
+    #[test]
+    fn most_specific_target_wins() {
+        let filter = filter_with(&[("crab_gl", LogLevel::Info), ("crab_gl::game", LogLevel::Debug), ("crab_gl::game::world", LogLevel::Error)]);
+        assert_eq!(filter.level_for("crab_gl"), LogLevel::Info);
+        assert_eq!(filter.level_for("crab_gl::renderer"), LogLevel::Info);
+        assert_eq!(filter.level_for("crab_gl::game::sph"), LogLevel::Debug);
+        assert_eq!(filter.level_for("crab_gl::game::world"), LogLevel::Error);
+        assert_eq!(filter.level_for("crab_gl::game::world::inner"), LogLevel::Error);
+        assert_eq!(filter.level_for("wgpu"), LogLevel::Warn);
+    }
+
+    #[test]
+    fn prefixes_only_match_whole_path_segments() {
+        let filter = filter_with(&[("crab_gl::game", LogLevel::Trace)]);
+        assert_eq!(filter.level_for("crab_gl::gamepad"), LogLevel::Warn);
+        assert_eq!(filter.level_for("crab_gl"), LogLevel::Warn);
+    }
+
+    #[test]
+    fn throttle_counts_what_it_drops() {
+        let mut throttle = Throttle::new();
+        assert_eq!(throttle.admit(0., 1000.), Some(0));
+        assert_eq!(throttle.admit(10., 1000.), None);
+        assert_eq!(throttle.admit(999., 1000.), None);
+        assert_eq!(throttle.admit(1000., 1000.), Some(2));
+        // the count starts over, and the interval runs from the last message that went out
+        assert_eq!(throttle.admit(1500., 1000.), None);
+        assert_eq!(throttle.admit(2000., 1000.), Some(1));
+        assert_eq!(throttle.admit(5000., 1000.), Some(0));
+    }
+}
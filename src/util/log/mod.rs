@@ -1,2 +1,2 @@
 pub mod logger;
-pub use logger::{log, LogLevel};
\ No newline at end of file
+pub use logger::{log, log_target, log_throttled, enabled, init, set_level, set_target_level, clear_target_levels, LogLevel};
\ No newline at end of file
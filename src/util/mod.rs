@@ -1,5 +1,6 @@
 pub mod error;
 pub use error::Error;
 pub mod log;
-pub use log::LogLevel;
-pub use log::log;
\ No newline at end of file
+pub use self::log::LogLevel;
+pub use self::log::log;
+pub mod profiler;
\ No newline at end of file
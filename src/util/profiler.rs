@@ -0,0 +1,120 @@
+use wasm_bindgen::prelude::*;
+
+pub const PROFILER_WINDOW: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProfilePhase {
+    Bounds,
+    Gravity,
+    Forces,
+    OverlapSolve,
+    Constraints,
+    GridRebuild,
+    InstanceUpload,
+    Draw,
+}
+
+const PHASE_COUNT: usize = 8;
+
+// milliseconds since an arbitrary origin. performance.now() in the browser, Instant everywhere else.
+#[cfg(target_arch = "wasm32")]
+pub fn now_ms() -> f64 {
+    web_sys::window()
+        .and_then(|window| window.performance())
+        .map_or(0., |performance| performance.now())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn now_ms() -> f64 {
+    use std::sync::OnceLock;
+    use std::time::Instant;
+    static ORIGIN: OnceLock<Instant> = OnceLock::new();
+    ORIGIN.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.
+}
+
+// Fixed size window of samples, averaged on read.
+#[derive(Debug, Clone)]
+struct RollingAverage {
+    samples: [f64; PROFILER_WINDOW],
+    next: usize,
+    count: usize,
+}
+
+impl RollingAverage {
+    fn new() -> Self {
+        RollingAverage { samples: [0.; PROFILER_WINDOW], next: 0, count: 0 }
+    }
+
+    fn push(&mut self, sample: f64) {
+        self.samples[self.next] = sample;
+        self.next = (self.next + 1) % PROFILER_WINDOW;
+        self.count = (self.count + 1).min(PROFILER_WINDOW);
+    }
+
+    fn average(&self) -> f64 {
+        if self.count == 0 {
+            return 0.;
+        }
+        self.samples[..self.count].iter().sum::<f64>() / self.count as f64
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    phases: [RollingAverage; PHASE_COUNT],
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler { phases: std::array::from_fn(|_| RollingAverage::new()) }
+    }
+
+    // record the time elapsed since `start_ms` (a value previously returned by now_ms) against a phase
+    pub fn record(&mut self, phase: ProfilePhase, start_ms: f64) {
+        self.push(phase, now_ms() - start_ms);
+    }
+
+    // record an already measured duration, for phases that run in several pieces per step
+    pub fn push(&mut self, phase: ProfilePhase, duration_ms: f64) {
+        self.phases[phase as usize].push(duration_ms);
+    }
+
+    pub fn average_ms(&self, phase: ProfilePhase) -> f64 {
+        self.phases[phase as usize].average()
+    }
+}
+
+// Rolling average duration of each profiled phase, in milliseconds.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PhaseTimings {
+    pub bounds_ms: f64,
+    pub gravity_ms: f64,
+    pub forces_ms: f64,
+    pub overlap_solve_ms: f64,
+    pub constraints_ms: f64,
+    pub grid_rebuild_ms: f64,
+    pub instance_upload_ms: f64,
+    pub draw_ms: f64,
+}
+
+impl PhaseTimings {
+    pub fn new(physics: &Profiler, renderer: &Profiler) -> PhaseTimings {
+        PhaseTimings {
+            bounds_ms: physics.average_ms(ProfilePhase::Bounds),
+            gravity_ms: physics.average_ms(ProfilePhase::Gravity),
+            forces_ms: physics.average_ms(ProfilePhase::Forces),
+            overlap_solve_ms: physics.average_ms(ProfilePhase::OverlapSolve),
+            constraints_ms: physics.average_ms(ProfilePhase::Constraints),
+            grid_rebuild_ms: physics.average_ms(ProfilePhase::GridRebuild),
+            instance_upload_ms: renderer.average_ms(ProfilePhase::InstanceUpload),
+            draw_ms: renderer.average_ms(ProfilePhase::Draw),
+        }
+    }
+}
@@ -1,32 +1,61 @@
 import init, { create_game } from "../pkg/crab_gl.js";
 
 const CANVAS_ID = "triangle";
-const FIXED_TIMESTEP = 0.01;
 let renderCanvas = document.getElementById(CANVAS_ID);
 let gameState;
-let date;
-let accumulator = 0.;
 
 init().then( () => {
-    date = new Date();
-    gameState = create_game(CANVAS_ID);
-    renderCanvas.addEventListener("click", e =>
-        gameState.on_click(e.offsetX, e.offsetY));
-    requestAnimationFrame(onFrame)
-});
-
-function onFrame() {
-    requestAnimationFrame(onFrame);
-    let now = new Date();
-    let elapsed = (now - date) / 1000;
-    accumulator += elapsed;
-    while (accumulator >= FIXED_TIMESTEP) {
-        gameState.update(FIXED_TIMESTEP);
-        accumulator -= FIXED_TIMESTEP;
+    try {
+        gameState = create_game(CANVAS_ID);
+    } catch (error) {
+        // error.name tells CanvasError, WebGlError and ShaderError apart
+        console.error(`${error.name}: ${error.message}`);
+        return;
     }
-    gameState.render();
-    date = now;
-}
+    // the stylesheet sizes the canvas, the drawing buffer follows it at the device pixel ratio
+    gameState.set_auto_resize(true);
+    // dragging pans, the wheel zooms around the cursor and a double click follows the particle under it
+    let dragDistance = 0;
+    renderCanvas.addEventListener("pointerdown", e => {
+        dragDistance = 0;
+        renderCanvas.setPointerCapture(e.pointerId);
+        gameState.on_drag_start(e.offsetX, e.offsetY);
+    });
+    renderCanvas.addEventListener("pointermove", e => {
+        if (renderCanvas.hasPointerCapture(e.pointerId)) {
+            dragDistance += Math.abs(e.movementX) + Math.abs(e.movementY);
+            gameState.on_drag_move(e.offsetX, e.offsetY);
+        }
+    });
+    renderCanvas.addEventListener("pointerup", () => gameState.on_drag_end());
+    renderCanvas.addEventListener("wheel", e => {
+        e.preventDefault();
+        // Firefox reports mouse wheels in lines (deltaMode 1) and some devices in pages (deltaMode 2)
+        const pixels = e.deltaMode === 1 ? 16 : e.deltaMode === 2 ? renderCanvas.clientHeight : 1;
+        gameState.on_wheel(e.offsetX, e.offsetY, e.deltaY * pixels);
+    }, { passive: false });
+    // a double click that ended a drag does not pick
+    renderCanvas.addEventListener("dblclick", e => {
+        if (dragDistance < 4) {
+            gameState.follow_particle(gameState.pick_particle(e.offsetX, e.offsetY));
+        }
+    });
+    // space pauses and resumes, while paused "." advances a single step, "0" shows the whole world again and "r"
+    // starts over with a new world
+    document.addEventListener("keydown", e => {
+        if (e.code === "Space") {
+            e.preventDefault();
+            gameState.is_paused() ? gameState.resume() : gameState.pause();
+        } else if (e.key === ".") {
+            gameState.step_once();
+        } else if (e.key === "0") {
+            gameState.reset_camera();
+        } else if (e.key === "r") {
+            gameState.reset_world();
+        }
+    });
+    gameState.start();
+});
 
 const colorChangerForm = document.getElementById("color-changer");
 colorChangerForm.addEventListener("submit", (e) => {